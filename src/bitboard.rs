@@ -0,0 +1,175 @@
+//==============================================================//
+//  BITBOARDS
+//==============================================================//
+use crate::square::*;
+
+/// Set of squares, bit `n` stands for the square with index `n` (a1 is bit 0)
+pub type Bitboard = u64;
+
+/// Knight jumps and sliding directions as (files, ranks) offsets
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [
+    (1, 2),
+    (1, -2),
+    (-1, 2),
+    (-1, -2),
+    (2, 1),
+    (2, -1),
+    (-2, 1),
+    (-2, -1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (-1, -1), (-1, 1), (1, -1)];
+const KING_OFFSETS: [(i32, i32); 8] = [
+    (0, 1),
+    (0, -1),
+    (1, 0),
+    (-1, 0),
+    (1, 1),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+];
+
+/// Squares attacked from each square, computed at compile time
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = leaper_attacks(&KNIGHT_OFFSETS);
+pub const KING_ATTACKS: [Bitboard; 64] = leaper_attacks(&KING_OFFSETS);
+/// Squares attacked by a white (0) or black (1) pawn
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    leaper_attacks(&[(-1, 1), (1, 1)]),
+    leaper_attacks(&[(-1, -1), (1, -1)]),
+];
+/// Squares from each square to the edge of the board, excluding the square
+/// itself, in the order of `ROOK_DIRECTIONS` then `BISHOP_DIRECTIONS`
+const RAYS: [[Bitboard; 64]; 8] = [
+    ray_attacks(ROOK_DIRECTIONS[0]),
+    ray_attacks(ROOK_DIRECTIONS[1]),
+    ray_attacks(ROOK_DIRECTIONS[2]),
+    ray_attacks(ROOK_DIRECTIONS[3]),
+    ray_attacks(BISHOP_DIRECTIONS[0]),
+    ray_attacks(BISHOP_DIRECTIONS[1]),
+    ray_attacks(BISHOP_DIRECTIONS[2]),
+    ray_attacks(BISHOP_DIRECTIONS[3]),
+];
+
+pub fn bit(square: Square) -> Bitboard {
+    1 << square.index()
+}
+
+/// Squares of a bitboard, from a1 to h8
+pub fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = Square> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let index = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(Square::from_index(index))
+    })
+}
+
+/// Squares a rook on `square` attacks, up to and including the first piece
+/// of `occupied` in each direction
+pub fn rook_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    (0..4).fold(0, |attacks, dir| attacks | ray(dir, square, occupied))
+}
+
+pub fn bishop_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    (4..8).fold(0, |attacks, dir| attacks | ray(dir, square, occupied))
+}
+
+pub fn queen_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+/// Ray in direction `dir` cut after its first blocker: the squares behind
+/// the blocker are the blocker's own ray in the same direction
+fn ray(dir: usize, square: Square, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[dir][square.index()];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    // Directions towards h8 meet the lowest bit first, the others the highest
+    let (files, ranks) = match dir {
+        0..=3 => ROOK_DIRECTIONS[dir],
+        _ => BISHOP_DIRECTIONS[dir - 4],
+    };
+    let blocker = if ranks > 0 || (ranks == 0 && files > 0) {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ RAYS[dir][blocker as usize]
+}
+
+const fn leaper_attacks(offsets: &[(i32, i32)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let mut i = 0;
+        while i < offsets.len() {
+            let file = (index % 8) as i32 + offsets[i].0;
+            let rank = (index / 8) as i32 + offsets[i].1;
+            if file >= 0 && file < 8 && rank >= 0 && rank < 8 {
+                table[index] |= 1 << (rank * 8 + file);
+            }
+            i += 1;
+        }
+        index += 1;
+    }
+    table
+}
+
+const fn ray_attacks((files, ranks): (i32, i32)) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let mut file = (index % 8) as i32 + files;
+        let mut rank = (index / 8) as i32 + ranks;
+        while file >= 0 && file < 8 && rank >= 0 && rank < 8 {
+            table[index] |= 1 << (rank * 8 + file);
+            file += files;
+            rank += ranks;
+        }
+        index += 1;
+    }
+    table
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn sq(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+    fn names(bitboard: Bitboard) -> Vec<String> {
+        squares(bitboard).map(|square| square.to_string()).collect()
+    }
+    #[test]
+    fn leaper_tables() {
+        assert_eq!(names(KNIGHT_ATTACKS[sq("a1").index()]), ["c2", "b3"]);
+        assert_eq!(KNIGHT_ATTACKS[sq("e4").index()].count_ones(), 8);
+        assert_eq!(names(KING_ATTACKS[sq("h8").index()]), ["g7", "h7", "g8"]);
+        assert_eq!(names(PAWN_ATTACKS[0][sq("a2").index()]), ["b3"]);
+        assert_eq!(names(PAWN_ATTACKS[1][sq("e7").index()]), ["d6", "f6"]);
+        assert_eq!(PAWN_ATTACKS[0][sq("e8").index()], 0);
+    }
+    #[test]
+    fn sliding_attacks_stop_at_blockers() {
+        assert_eq!(rook_attacks(sq("a1"), 0).count_ones(), 14);
+        assert_eq!(bishop_attacks(sq("d4"), 0).count_ones(), 13);
+        let occupied = bit(sq("d6")) | bit(sq("b4")) | bit(sq("f2")) | bit(sq("h4"));
+        assert_eq!(
+            names(rook_attacks(sq("d4"), occupied)),
+            ["d1", "d2", "d3", "b4", "c4", "e4", "f4", "g4", "h4", "d5", "d6"]
+        );
+        assert_eq!(
+            names(bishop_attacks(sq("d4"), occupied)),
+            ["a1", "b2", "f2", "c3", "e3", "c5", "e5", "b6", "f6", "a7", "g7", "h8"]
+        );
+    }
+}
@@ -2,31 +2,128 @@
 //  STRUCTS AND ENUMS
 //==============================================================//
 use super::log;
+use crate::bitboard::*;
+use crate::square::*;
+use crate::zobrist::*;
 /// Grid representation of the chess board
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChessBoard {
-    pub board: [[Option<usize>; 8]; 8],
+    /// Index in `pieces` of the piece on each square
+    pub board: [Option<usize>; 64],
     pub pieces: [ChessPiece; 32],
+    /// Squares of each piece type, indexed by `PieceType::index`
+    pub by_type: [Bitboard; 6],
+    /// Squares of each color, indexed by `PieceColor::index`
+    pub by_color: [Bitboard; 2],
+    pub castling: CastlingRights,
+    /// Player whose turn it is
+    pub side_to_move: PieceColor,
+    /// Square skipped by a pawn that just advanced two squares
+    pub en_passant: Option<Square>,
+    /// Moves since the last capture or pawn move, for the fifty-move rule
+    pub halfmove_clock: u32,
+    /// Starts at 1 and is incremented after each black move
+    pub fullmove_number: u32,
+    /// Zobrist key of the position, updated move by move
+    pub hash: u64,
+    /// Keys of the positions reached, the last one is the current position
+    pub history: Vec<u64>,
 }
-#[derive(Clone, Debug)]
+/// Which castling moves are still available to each side
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChessPiece {
     pub piece_color: PieceColor,
     pub piece_type: PieceType,
     pub id: usize,
-    pub position: Option<(usize, usize)>,
+    pub position: Option<Square>,
+}
+/// A move with the pieces involved, as produced by the move generator
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub piece: PieceType,
+    /// Piece taken by the move, a pawn for en passant
+    pub captured: Option<PieceType>,
+    pub promotion: Option<PieceType>,
+    pub kind: MoveKind,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MoveKind {
+    Normal,
+    /// Pawn advancing two squares from its starting rank
+    DoublePush,
+    EnPassant,
+    /// King moving two squares, the side is given by the destination
+    Castle,
+}
+/// What `make_move` changed that can't be deduced from the move itself, to
+/// take it back with `unmake_move`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Undo {
+    /// Index in `pieces` of the captured piece
+    captured: Option<usize>,
+    castling: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    hash: u64,
+}
+/// A move accepted by `try_make_move`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveOutcome {
+    pub mov: Move,
+    /// Needed to take the move back with `unmake_move`
+    pub undo: Undo,
+    /// State of the game after the move
+    pub status: GameStatus,
+}
+/// Why `try_make_move` refused a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    /// There is no piece on the origin square
+    EmptySquare,
+    /// The piece belongs to the player who isn't on move
+    WrongSide,
+    /// The piece can't go there, or the move leaves its king in check
+    IllegalMove,
+    /// The game already ended by checkmate, stalemate or a draw
+    GameOver,
 }
 /// convenient builder to create chess pieces
 struct CPB {
     current_id: usize,
     color: PieceColor,
 }
+/// State of the game for the player about to move
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The game is over, the color is the winner
+    Checkmate(PieceColor),
+    Stalemate,
+    Draw(DrawReason),
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    InsufficientMaterial,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PieceColor {
     White,
     Black,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PieceType {
     Pawn,
     Bishop,
@@ -39,14 +136,19 @@ pub enum PieceType {
 //==============================================================//
 //  IMPLEMENTATIONS
 //==============================================================//
+impl Move {
+    pub fn is_capture(&self) -> bool {
+        self.captured.is_some()
+    }
+}
+
 impl ToString for ChessBoard {
     fn to_string(&self) -> String {
         let mut buffer = String::from("|--|--|--|--|--|--|--|--|\n");
-        for row in &self.board {
+        for rank in (0..8).rev() {
             buffer += "|";
-            for item in row {
-                if let Some(pi) = item {
-                    let piece = &self.pieces[*pi];
+            for file in 0..8 {
+                if let Some(piece) = self.get(Square::new(file, rank)) {
                     buffer += &format!("{}", piece);
                 } else {
                     buffer += "  ";
@@ -97,712 +199,604 @@ impl ChessBoard {
                 b.white().knight(),
                 b.white().rook(),
             ],
-            board: [
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-            ],
+            board: [None; 64],
+            by_type: [0; 6],
+            by_color: [0; 2],
+            castling: CastlingRights::all(),
+            side_to_move: PieceColor::White,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            history: vec![],
         };
         for i in 0..cb.pieces.len() {
-            let y = cb.pieces[i].position.unwrap().1;
-            let x = cb.pieces[i].position.unwrap().0;
-
-            cb.board[y][x] = Some(i);
+            let square = cb.pieces[i].position.unwrap();
+            cb.board[square.index()] = Some(i);
         }
+        cb.update_bitboards();
+        cb.hash = cb.position_key();
+        cb.history.push(cb.hash);
         cb
     }
-    pub fn make_move(&mut self, origin: (usize, usize), destination: (usize, usize)) {
-        let piece = self.board[origin.1][origin.0];
-        self.board[origin.1][origin.0] = None;
-        let destpiece = self.board[destination.1][destination.0];
-        /* super::log(&format!(
-            "{:?}[{:?}]->{:?}[{:?}]",
-            piece, destpiece, origin, destination
-        )); */
-        if let Some(pi) = piece {
-            let p = self.pieces.get_mut(pi).unwrap();
-            p.position = Some(destination);
-            self.board[destination.1][destination.0] = Some(pi);
-        }
-        if let Some(dpi) = destpiece {
-            let p = self.pieces.get_mut(dpi).unwrap();
-            p.position = None;
-        }
-    }
-    pub fn get(&self, x: usize, y: usize) -> Option<&ChessPiece> {
-        let index = self.board[y][x];
-        if let Some(i) = index {
-            Some(&self.pieces[i])
+    /// Describes moving the piece on `from` to `to`, a pawn reaching the last
+    /// rank turns into `promotion` (a queen if none is given).
+    /// None when `from` is empty, the move itself isn't validated.
+    pub fn new_move(&self, from: Square, to: Square, promotion: Option<PieceType>) -> Option<Move> {
+        let piece = self.get(from)?.piece_type;
+        let mut captured = self.get(to).map(|p| p.piece_type);
+        let kind = match piece {
+            PieceType::King if from.file().abs_diff(to.file()) == 2 => MoveKind::Castle,
+            PieceType::Pawn if from.rank().abs_diff(to.rank()) == 2 => MoveKind::DoublePush,
+            // Pawns only move diagonally to an empty square en passant
+            PieceType::Pawn if from.file() != to.file() && captured.is_none() => {
+                captured = Some(PieceType::Pawn);
+                MoveKind::EnPassant
+            }
+            _ => MoveKind::Normal,
+        };
+        let promotion = if self.is_promotion(from, to) {
+            Some(promotion.unwrap_or(PieceType::Queen))
         } else {
             None
-        }
+        };
+        Some(Move {
+            from,
+            to,
+            piece,
+            captured,
+            promotion,
+            kind,
+        })
     }
-
-    pub fn can_move(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> bool {
-        self.get_possible_moves(x0, y0).contains(&(x1, y1))
+    /// Checks that the move is legal before making it, unlike `make_move`
+    pub fn try_make_move(
+        &mut self,
+        from: Square,
+        to: Square,
+        promotion: Option<PieceType>,
+    ) -> Result<MoveOutcome, MoveError> {
+        if self.status() != GameStatus::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+        match self.get(from) {
+            None => return Err(MoveError::EmptySquare),
+            Some(p) if p.piece_color != self.side_to_move => return Err(MoveError::WrongSide),
+            Some(_) => {}
+        }
+        if promotion.is_some() && !self.is_promotion(from, to) {
+            return Err(MoveError::IllegalMove);
+        }
+        let mov = self
+            .new_move(from, to, promotion)
+            .filter(|mov| self.get_legal_moves(from).contains(mov))
+            .ok_or(MoveError::IllegalMove)?;
+        let undo = self.make_move(mov);
+        Ok(MoveOutcome {
+            mov,
+            undo,
+            status: self.status(),
+        })
     }
-    pub fn get_possible_moves(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let piece = self.get(x, y).unwrap();
-        match piece.piece_type {
-            PieceType::Pawn => self.get_pawn_moves(&piece, x, y),
-            PieceType::Knight => self.get_knight_moves(&piece, x, y),
-            PieceType::Bishop => self.get_bishop_moves(&piece, x, y),
-            PieceType::Rook => self.get_rook_moves(&piece, x, y),
-            PieceType::Queen => self.get_queen_moves(&piece, x, y),
-            PieceType::King => self.get_king_moves(&piece, x, y),
-        }
-    }
-    pub fn get_pawn_moves(&self, piece: &ChessPiece, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let mut position = vec![];
-        match piece.piece_color {
-            PieceColor::Black => {
-                if y < 7 {
-                    if self.get(x, y + 1).is_none() {
-                        position.push((x, y + 1))
-                    }
-                    if x < 7 {
-                        if let Some(p) = self.get(x + 1, y + 1) {
-                            if p.piece_color != piece.piece_color {
-                                position.push((x + 1, y + 1))
-                            }
-                        }
-                    }
-                    if x > 0 {
-                        if let Some(p) = self.get(x - 1, y + 1) {
-                            if p.piece_color != piece.piece_color {
-                                position.push((x - 1, y + 1))
-                            }
-                        }
-                    }
+    /// Makes a move and passes the turn to the other player.
+    /// The move isn't validated, use `try_make_move` for moves coming from
+    /// outside the move generator.
+    pub fn make_move(&mut self, mov: Move) -> Undo {
+        let (origin, destination) = (mov.from, mov.to);
+        let captured = if mov.is_capture() {
+            self.board[self.captured_square(&mov).index()]
+        } else {
+            None
+        };
+        let undo = Undo {
+            captured,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        self.hash ^= en_passant_key(self.capturable_en_passant());
+        let mut en_passant = None;
+        match mov.kind {
+            // Castling: the king moves two squares, the rook jumps over it
+            MoveKind::Castle => {
+                let rank = origin.rank();
+                if destination.file() > origin.file() {
+                    self.move_piece(Square::new(7, rank), Square::new(5, rank));
+                } else {
+                    self.move_piece(Square::new(0, rank), Square::new(3, rank));
                 }
             }
-            PieceColor::White => {
-                if y > 0 {
-                    if self.get(x, y - 1).is_none() {
-                        position.push((x, y - 1))
-                    }
-                    if x < 7 {
-                        if let Some(p) = self.get(x + 1, y - 1) {
-                            if p.piece_color != piece.piece_color {
-                                position.push((x + 1, y - 1))
-                            }
-                        }
-                    }
-                    if x > 0 {
-                        if let Some(p) = self.get(x - 1, y - 1) {
-                            if p.piece_color != piece.piece_color {
-                                position.push((x - 1, y - 1))
-                            }
-                        }
-                    }
-                }
+            // En passant: the captured pawn is beside the origin square
+            MoveKind::EnPassant => self.remove_piece(self.captured_square(&mov)),
+            MoveKind::DoublePush => {
+                let rank = (origin.rank() + destination.rank()) / 2;
+                en_passant = Some(Square::new(origin.file(), rank));
             }
+            MoveKind::Normal => {}
         }
-        position
-    }
-    pub fn get_knight_moves(&self, piece: &ChessPiece, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let mut ret = vec![];
-        if self.is_position_valid(Some(x + 1), Some(y + 2), piece.piece_color) {
-            ret.push((x + 1, y + 2))
+        self.move_piece(origin, destination);
+        if let Some(promotion) = mov.promotion {
+            self.change_piece_type(destination, promotion);
         }
-        if self.is_position_valid(Some(x + 1), y.checked_sub(2), piece.piece_color) {
-            ret.push((x + 1, y - 2))
+        self.hash ^= castling_key(self.castling);
+        self.update_castling_rights(origin, destination);
+        self.en_passant = en_passant;
+        self.hash ^= castling_key(self.castling);
+        if self.side_to_move == PieceColor::Black {
+            self.fullmove_number += 1;
         }
-        if self.is_position_valid(x.checked_sub(1), Some(y + 2), piece.piece_color) {
-            ret.push((x - 1, y + 2))
+        self.side_to_move = self.side_to_move.opponent();
+        self.hash ^= side_key() ^ en_passant_key(self.capturable_en_passant());
+        if mov.piece == PieceType::Pawn || mov.is_capture() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
         }
-        if self.is_position_valid(x.checked_sub(1), y.checked_sub(2), piece.piece_color) {
-            ret.push((x - 1, y - 2))
+        self.history.push(self.hash);
+        undo
+    }
+    /// Takes back the last move made, restoring the board exactly as it was
+    pub fn unmake_move(&mut self, mov: Move, undo: Undo) {
+        self.history.pop();
+        self.side_to_move = self.side_to_move.opponent();
+        if self.side_to_move == PieceColor::Black {
+            self.fullmove_number -= 1;
         }
-        if self.is_position_valid(Some(x + 2), Some(y + 1), piece.piece_color) {
-            ret.push((x + 2, y + 1))
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+
+        self.move_piece(mov.to, mov.from);
+        if mov.promotion.is_some() {
+            self.change_piece_type(mov.from, PieceType::Pawn);
         }
-        if self.is_position_valid(Some(x + 2), y.checked_sub(1), piece.piece_color) {
-            ret.push((x + 2, y - 1))
+        if mov.kind == MoveKind::Castle {
+            let rank = mov.from.rank();
+            if mov.to.file() > mov.from.file() {
+                self.move_piece(Square::new(5, rank), Square::new(7, rank));
+            } else {
+                self.move_piece(Square::new(3, rank), Square::new(0, rank));
+            }
         }
-        if self.is_position_valid(x.checked_sub(2), Some(y + 1), piece.piece_color) {
-            ret.push((x - 2, y + 1))
+        if let Some(pi) = undo.captured {
+            self.place_piece(pi, self.captured_square(&mov));
         }
-        if self.is_position_valid(x.checked_sub(2), y.checked_sub(1), piece.piece_color) {
-            ret.push((x - 2, y - 1))
+        self.hash = undo.hash;
+    }
+    /// Square of the piece taken by a move, beside the origin for en passant
+    fn captured_square(&self, mov: &Move) -> Square {
+        match mov.kind {
+            MoveKind::EnPassant => Square::new(mov.to.file(), mov.from.rank()),
+            _ => mov.to,
         }
-        ret
     }
-    pub fn get_bishop_moves(&self, piece: &ChessPiece, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let mut ret = vec![];
-        let mut cx = x;
-        let mut cy = y;
-        let mycolor = piece.piece_color;
-        // BOTTOM_RIGHT
-        while cy < 7 && cx < 7 {
-            cy += 1;
-            cx += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+    /// The en passant square if a pawn of the side to move can capture on it,
+    /// otherwise it doesn't change the position
+    fn capturable_en_passant(&self) -> Option<Square> {
+        self.en_passant.filter(|target| {
+            PAWN_ATTACKS[self.side_to_move.opponent().index()][target.index()]
+                & self.pieces_of(self.side_to_move, PieceType::Pawn)
+                != 0
+        })
+    }
+    /// Zobrist key of the piece placement, side to move, castling rights and
+    /// en passant square, computed from scratch unlike `hash`
+    pub fn position_key(&self) -> u64 {
+        let mut key = castling_key(self.castling) ^ en_passant_key(self.capturable_en_passant());
+        if self.side_to_move == PieceColor::Black {
+            key ^= side_key();
         }
-        cx = x;
-        cy = y;
-        // TOP-LEFT
-        while cy > 0 && cx > 0 {
-            cy -= 1;
-            cx -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+        for square in Square::all() {
+            if let Some(p) = self.get(square) {
+                key ^= piece_key(p.piece_color, p.piece_type, square);
             }
         }
-        cx = x;
-        cy = y;
-        // BOTTOM-LEFT
-        while cy < 7 && cx > 0 {
-            cy += 1;
-            cx -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+        key
+    }
+    /// How many times the current position occurred. Positions before the
+    /// last capture or pawn move can't come back, so they're skipped.
+    pub fn repetitions(&self) -> usize {
+        match self.history.last() {
+            Some(current) => self
+                .history
+                .iter()
+                .rev()
+                .take(self.halfmove_clock as usize + 1)
+                .filter(|key| *key == current)
+                .count(),
+            None => 0,
         }
-        cx = x;
-        cy = y;
-        // TOP-RIGHT
-        while cy > 0 && cx < 7 {
-            cy -= 1;
-            cx += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
+    }
+    /// Whether neither side can possibly checkmate: K vs K, K+minor vs K and
+    /// kings with bishops all standing on the same square color
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut minors = vec![];
+        for piece in &self.pieces {
+            if let Some(square) = piece.position {
+                match piece.piece_type {
+                    PieceType::King => {}
+                    PieceType::Knight | PieceType::Bishop => {
+                        minors.push((piece.piece_type, square))
                     }
+                    _ => return false,
                 }
             }
         }
-        ret
+        let shade = |square: &Square| (square.file() + square.rank()) % 2;
+        match minors.as_slice() {
+            [] | [_] => true,
+            [(_, first), ..] => minors
+                .iter()
+                .all(|(t, square)| *t == PieceType::Bishop && shade(square) == shade(first)),
+        }
     }
-    pub fn get_rook_moves(&self, piece: &ChessPiece, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let mut ret = vec![];
-        let mut cx = x;
-        let mut cy = y;
-        let mycolor = piece.piece_color;
-        // BOTTOM
-        while cy < 7 {
-            cy += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+    /// Draws that don't depend on the available moves
+    pub fn draw_reason(&self) -> Option<DrawReason> {
+        let repetitions = self.repetitions();
+        if self.is_insufficient_material() {
+            Some(DrawReason::InsufficientMaterial)
+        } else if repetitions >= 5 {
+            Some(DrawReason::FivefoldRepetition)
+        } else if repetitions >= 3 {
+            Some(DrawReason::ThreefoldRepetition)
+        } else if self.halfmove_clock >= 100 {
+            Some(DrawReason::FiftyMoveRule)
+        } else {
+            None
         }
-        cx = x;
-        cy = y;
-        // TOP
-        while cy > 0 {
-            cy -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+    }
+    /// Whether moving the piece on `origin` to `destination` promotes a pawn
+    pub fn is_promotion(&self, origin: Square, destination: Square) -> bool {
+        match self.get(origin) {
+            Some(ChessPiece {
+                piece_type: PieceType::Pawn,
+                piece_color: PieceColor::White,
+                ..
+            }) => destination.rank() == 7,
+            Some(ChessPiece {
+                piece_type: PieceType::Pawn,
+                piece_color: PieceColor::Black,
+                ..
+            }) => destination.rank() == 0,
+            _ => false,
         }
-        cx = x;
-        cy = y;
-        // LEFT
-        while cx > 0 {
-            cx -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+    }
+    /// Rebuilds the bitboards from the pieces, the moves then keep them up to
+    /// date
+    pub fn update_bitboards(&mut self) {
+        self.by_type = [0; 6];
+        self.by_color = [0; 2];
+        for piece in &self.pieces {
+            if let Some(square) = piece.position {
+                self.by_type[piece.piece_type.index()] |= bit(square);
+                self.by_color[piece.piece_color.index()] |= bit(square);
             }
         }
-        cx = x;
-        cy = y;
-        // RIGHT
-        while cx < 7 {
-            cx += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+    }
+    /// Flips the square in the bitboards and the key of the piece
+    fn toggle_bits(&mut self, pi: usize, square: Square) {
+        let piece = &self.pieces[pi];
+        self.by_type[piece.piece_type.index()] ^= bit(square);
+        self.by_color[piece.piece_color.index()] ^= bit(square);
+        self.hash ^= piece_key(piece.piece_color, piece.piece_type, square);
+    }
+    fn place_piece(&mut self, pi: usize, square: Square) {
+        self.pieces[pi].position = Some(square);
+        self.board[square.index()] = Some(pi);
+        self.toggle_bits(pi, square);
+    }
+    fn remove_piece(&mut self, square: Square) {
+        if let Some(pi) = self.board[square.index()].take() {
+            self.pieces[pi].position = None;
+            self.toggle_bits(pi, square);
         }
-        ret
     }
-    pub fn get_queen_moves(&self, piece: &ChessPiece, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let mut ret = vec![];
-        let mut cx = x;
-        let mut cy = y;
-        let mycolor = piece.piece_color;
-        // BOTTOM
-        while cy < 7 {
-            cy += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+    fn move_piece(&mut self, origin: Square, destination: Square) {
+        self.remove_piece(destination);
+        if let Some(pi) = self.board[origin.index()].take() {
+            self.toggle_bits(pi, origin);
+            self.place_piece(pi, destination);
         }
-        cx = x;
-        cy = y;
-        // TOP
-        while cy > 0 {
-            cy -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-            }
+    }
+    fn change_piece_type(&mut self, square: Square, piece_type: PieceType) {
+        if let Some(pi) = self.board[square.index()] {
+            self.toggle_bits(pi, square);
+            self.pieces[pi].piece_type = piece_type;
+            self.toggle_bits(pi, square);
         }
-        cx = x;
-        cy = y;
-        // LEFT
-        while cx > 0 {
-            cx -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
+    }
+    /// Castling rights are lost when the king or a rook leaves its starting
+    /// square, and when a rook is captured on its starting square.
+    fn update_castling_rights(&mut self, origin: Square, destination: Square) {
+        for square in &[origin, destination] {
+            match (square.file(), square.rank()) {
+                (4, 0) => {
+                    self.castling.white_king_side = false;
+                    self.castling.white_queen_side = false;
                 }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
+                (4, 7) => {
+                    self.castling.black_king_side = false;
+                    self.castling.black_queen_side = false;
                 }
+                (7, 0) => self.castling.white_king_side = false,
+                (0, 0) => self.castling.white_queen_side = false,
+                (7, 7) => self.castling.black_king_side = false,
+                (0, 7) => self.castling.black_queen_side = false,
+                _ => {}
             }
         }
-        cx = x;
-        cy = y;
-        // RIGHT
-        while cx < 7 {
-            cx += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+    }
+    pub fn get(&self, square: Square) -> Option<&ChessPiece> {
+        let index = self.board[square.index()];
+        if let Some(i) = index {
+            Some(&self.pieces[i])
+        } else {
+            None
+        }
+    }
+
+    pub fn can_move(&self, from: Square, to: Square) -> bool {
+        match self.get(from) {
+            Some(p) if p.piece_color == self.side_to_move => {
+                self.get_legal_moves(from).iter().any(|mov| mov.to == to)
             }
+            _ => false,
         }
-        cx = x;
-        cy = y;
-        // BOTTOM_RIGHT
-        while cy < 7 && cx < 7 {
-            cy += 1;
-            cx += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+    }
+    /// Possible moves that don't leave the player's own king in check, none
+    /// for an empty square
+    pub fn get_legal_moves(&self, square: Square) -> Vec<Move> {
+        let color = match self.get(square) {
+            Some(piece) => piece.piece_color,
+            None => return vec![],
+        };
+        let mut board = self.clone();
+        self.get_possible_moves(square)
+            .into_iter()
+            .filter(|mov| board.is_legal(*mov, color))
+            .collect()
+    }
+    /// Legal moves of every piece of the side to move, checked by playing
+    /// them on this board and taking them back
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let color = self.side_to_move;
+        self.pseudo_legal_moves(color)
+            .into_iter()
+            .filter(|mov| self.is_legal(*mov, color))
+            .collect()
+    }
+    /// Possible moves of every piece of `color`, ignoring checks
+    fn pseudo_legal_moves(&self, color: PieceColor) -> Vec<Move> {
+        self.pieces
+            .iter()
+            .filter(|p| p.piece_color == color)
+            .filter_map(|p| p.position)
+            .flat_map(|square| self.get_possible_moves(square))
+            .collect()
+    }
+    /// Whether a move of `color` keeps its king out of check
+    fn is_legal(&mut self, mov: Move, color: PieceColor) -> bool {
+        let undo = self.make_move(mov);
+        let legal = !self.is_in_check(color);
+        self.unmake_move(mov, undo);
+        legal
+    }
+    pub fn is_in_check(&self, color: PieceColor) -> bool {
+        match self.king_position(color) {
+            Some(square) => self.is_square_attacked(square, color.opponent()),
+            None => false,
+        }
+    }
+    pub fn has_legal_moves(&self, color: PieceColor) -> bool {
+        let mut board = self.clone();
+        self.pseudo_legal_moves(color)
+            .into_iter()
+            .any(|mov| board.is_legal(mov, color))
+    }
+    pub fn status(&self) -> GameStatus {
+        let to_move = self.side_to_move;
+        if self.has_legal_moves(to_move) {
+            match self.draw_reason() {
+                Some(reason) => GameStatus::Draw(reason),
+                None => GameStatus::Ongoing,
             }
+        } else if self.is_in_check(to_move) {
+            GameStatus::Checkmate(to_move.opponent())
+        } else {
+            GameStatus::Stalemate
         }
-        cx = x;
-        cy = y;
-        // TOP-LEFT
-        while cy > 0 && cx > 0 {
-            cy -= 1;
-            cx -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+    }
+    pub fn king_position(&self, color: PieceColor) -> Option<Square> {
+        squares(self.pieces_of(color, PieceType::King)).next()
+    }
+    /// Moves of the piece on the square, ignoring checks. A pawn reaching the
+    /// last rank gets one move per promotion piece, an empty square has none.
+    pub fn get_possible_moves(&self, square: Square) -> Vec<Move> {
+        let mut moves = vec![];
+        for destination in self.get_destinations(square) {
+            if self.is_promotion(square, destination) {
+                moves.extend(
+                    PROMOTION_PIECES
+                        .iter()
+                        .filter_map(|p| self.new_move(square, destination, Some(*p))),
+                );
+            } else {
+                moves.extend(self.new_move(square, destination, None));
             }
         }
-        cx = x;
-        cy = y;
-        // BOTTOM-LEFT
-        while cy < 7 && cx > 0 {
-            cy += 1;
-            cx -= 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+        moves
+    }
+    fn get_destinations(&self, square: Square) -> Vec<Square> {
+        let piece = match self.get(square) {
+            Some(piece) => piece,
+            None => return vec![],
+        };
+        match piece.piece_type {
+            PieceType::Pawn => self.get_pawn_moves(piece, square),
+            PieceType::Knight => self.get_knight_moves(piece, square),
+            PieceType::Bishop => self.get_bishop_moves(piece, square),
+            PieceType::Rook => self.get_rook_moves(piece, square),
+            PieceType::Queen => self.get_queen_moves(piece, square),
+            PieceType::King => self.get_king_moves(piece, square),
+        }
+    }
+    pub fn get_pawn_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
+        let color = piece.piece_color;
+        let empty = !self.occupied();
+        let (forward, start_rank) = match color {
+            PieceColor::White => (1, 1),
+            PieceColor::Black => (-1, 6),
+        };
+        let mut targets = 0;
+        if let Some(one) = square
+            .offset(0, forward)
+            .filter(|one| empty & bit(*one) != 0)
+        {
+            targets |= bit(one);
+            if let Some(two) = one
+                .offset(0, forward)
+                .filter(|_| square.rank() == start_rank)
+            {
+                targets |= bit(two) & empty;
             }
         }
-        cx = x;
-        cy = y;
-        // TOP-RIGHT
-        while cy > 0 && cx < 7 {
-            cy -= 1;
-            cx += 1;
-            match self.get(cx, cy) {
-                None => ret.push((cx, cy)),
-                Some(ChessPiece {
-                    piece_color: PieceColor::Black,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::Black {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
-                Some(ChessPiece {
-                    piece_color: PieceColor::White,
-                    ..
-                }) => {
-                    if mycolor == PieceColor::White {
-                        break;
-                    } else {
-                        ret.push((cx, cy));
-                        break;
-                    }
-                }
+        let mut enemies = self.by_color[color.opponent().index()];
+        // EN PASSANT
+        if let Some(target) = self.en_passant {
+            let skipped = Square::new(target.file(), square.rank());
+            if self.pieces_of(color.opponent(), PieceType::Pawn) & bit(skipped) != 0 {
+                enemies |= bit(target);
             }
         }
+        targets |= PAWN_ATTACKS[color.index()][square.index()] & enemies;
+        squares(targets).collect()
+    }
+    pub fn get_knight_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
+        self.get_targets(piece, KNIGHT_ATTACKS[square.index()])
+    }
+    pub fn get_bishop_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
+        self.get_targets(piece, bishop_attacks(square, self.occupied()))
+    }
+    pub fn get_rook_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
+        self.get_targets(piece, rook_attacks(square, self.occupied()))
+    }
+    pub fn get_queen_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
+        self.get_targets(piece, queen_attacks(square, self.occupied()))
+    }
+    pub fn get_king_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
+        let mut ret = self.get_targets(piece, KING_ATTACKS[square.index()]);
+        ret.extend(self.get_castling_moves(piece, square));
         ret
     }
-    pub fn get_king_moves(&self, piece: &ChessPiece, x: usize, y: usize) -> Vec<(usize, usize)> {
+    /// Attacked squares a piece can land on: empty or holding an enemy piece
+    fn get_targets(&self, piece: &ChessPiece, attacks: Bitboard) -> Vec<Square> {
+        squares(attacks & !self.by_color[piece.piece_color.index()]).collect()
+    }
+    fn get_castling_moves(&self, piece: &ChessPiece, square: Square) -> Vec<Square> {
         let mut ret = vec![];
-        if self.is_position_valid(Some(x), Some(y + 1), piece.piece_color) {
-            ret.push((x, y + 1))
-        }
-        if self.is_position_valid(Some(x), y.checked_sub(1), piece.piece_color) {
-            ret.push((x, y - 1))
-        }
-        if self.is_position_valid(Some(x + 1), Some(y), piece.piece_color) {
-            ret.push((x + 1, y))
-        }
-        if self.is_position_valid(x.checked_sub(1), Some(y), piece.piece_color) {
-            ret.push((x - 1, y))
-        }
-        if self.is_position_valid(Some(x + 1), Some(y + 1), piece.piece_color) {
-            ret.push((x + 1, y + 1))
-        }
-        if self.is_position_valid(Some(x + 1), y.checked_sub(1), piece.piece_color) {
-            ret.push((x + 1, y - 1))
+        let color = piece.piece_color;
+        let enemy = color.opponent();
+        let rank = match color {
+            PieceColor::White => 0,
+            PieceColor::Black => 7,
+        };
+        let free = |file| self.get(Square::new(file, rank)).is_none();
+        let safe = |file| !self.is_square_attacked(Square::new(file, rank), enemy);
+        // The king can neither castle out of check nor through an attacked square
+        if square != Square::new(4, rank) || !safe(4) {
+            return ret;
         }
-        if self.is_position_valid(x.checked_sub(1), Some(y + 1), piece.piece_color) {
-            ret.push((x - 1, y + 1))
+        if self.castling.king_side(color) && (5..7).all(free) && (5..7).all(safe) {
+            ret.push(Square::new(6, rank))
         }
-        if self.is_position_valid(x.checked_sub(1), y.checked_sub(1), piece.piece_color) {
-            ret.push((x - 1, y - 1))
+        if self.castling.queen_side(color) && (1..4).all(free) && (2..4).all(safe) {
+            ret.push(Square::new(2, rank))
         }
         ret
     }
-
-    fn is_position_valid(&self, x: Option<usize>, y: Option<usize>, color: PieceColor) -> bool {
-        if let (Some(xv), Some(yv)) = (x, y) {
-            if xv >= 8 || yv >= 8 {
-                return false;
-            }
-            if let Some(p) = self.get(xv, yv) {
-                p.piece_color != color
-            } else {
-                true
-            }
-        } else {
-            false
-        }
+    /// Whether any piece of color `by` attacks the square
+    pub fn is_square_attacked(&self, square: Square, by: PieceColor) -> bool {
+        let attackers = |piece_type| self.pieces_of(by, piece_type);
+        let occupied = self.occupied();
+        let index = square.index();
+        // A square is attacked by the pawns a pawn of the other color would
+        // attack from it
+        PAWN_ATTACKS[by.opponent().index()][index] & attackers(PieceType::Pawn) != 0
+            || KNIGHT_ATTACKS[index] & attackers(PieceType::Knight) != 0
+            || KING_ATTACKS[index] & attackers(PieceType::King) != 0
+            || rook_attacks(square, occupied)
+                & (attackers(PieceType::Rook) | attackers(PieceType::Queen))
+                != 0
+            || bishop_attacks(square, occupied)
+                & (attackers(PieceType::Bishop) | attackers(PieceType::Queen))
+                != 0
+    }
+    /// Squares holding a piece of the given color and type
+    pub fn pieces_of(&self, color: PieceColor, piece_type: PieceType) -> Bitboard {
+        self.by_color[color.index()] & self.by_type[piece_type.index()]
+    }
+    /// Squares holding a piece of either color
+    pub fn occupied(&self) -> Bitboard {
+        self.by_color[0] | self.by_color[1]
     }
 }
 
+/// Pieces a pawn can be promoted to
+pub const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
 impl Default for ChessBoard {
     fn default() -> ChessBoard {
         ChessBoard::new()
     }
 }
 
+impl CastlingRights {
+    pub fn all() -> Self {
+        CastlingRights {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+        }
+    }
+    pub fn king_side(&self, color: PieceColor) -> bool {
+        match color {
+            PieceColor::White => self.white_king_side,
+            PieceColor::Black => self.black_king_side,
+        }
+    }
+    pub fn queen_side(&self, color: PieceColor) -> bool {
+        match color {
+            PieceColor::White => self.white_queen_side,
+            PieceColor::Black => self.black_queen_side,
+        }
+    }
+}
+
+impl PieceColor {
+    /// Index of the color in `ChessBoard::by_color`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+    pub fn opponent(self) -> Self {
+        match self {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        }
+    }
+}
+impl PieceType {
+    /// Index of the type in `ChessBoard::by_type`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
 impl CPB {
     pub fn new() -> Self {
         Self {
@@ -838,29 +832,43 @@ impl CPB {
     }
     pub fn create_piece(&mut self, piece_type: PieceType) -> ChessPiece {
         self.current_id += 1;
-        let mut row = (self.current_id - 1) / 8;
-        if row > 1 {
-            row += 4;
+        // Black's back rank and pawns come first
+        let mut rank = 7 - (self.current_id - 1) / 8;
+        if rank < 6 {
+            rank -= 4;
         }
         ChessPiece {
             piece_color: self.color,
             piece_type,
             id: self.current_id,
-            position: Some(((self.current_id - 1) % 8, row)),
+            position: Some(Square::new((self.current_id - 1) % 8, rank)),
         }
     }
 }
 impl ChessPiece {}
 impl std::fmt::Display for ChessPiece {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.piece_color, self.piece_type)
+    }
+}
+impl std::fmt::Display for PieceColor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}{}",
-            match self.piece_color {
+            "{}",
+            match self {
                 PieceColor::Black => "B",
                 PieceColor::White => "W",
-            },
-            match self.piece_type {
+            }
+        )
+    }
+}
+impl std::fmt::Display for PieceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
                 PieceType::Pawn => "P",
                 PieceType::Knight => "k",
                 PieceType::Bishop => "B",
@@ -871,6 +879,17 @@ impl std::fmt::Display for ChessPiece {
         )
     }
 }
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::EmptySquare => write!(f, "no piece on that square"),
+            MoveError::WrongSide => write!(f, "not your piece"),
+            MoveError::IllegalMove => write!(f, "illegal move"),
+            MoveError::GameOver => write!(f, "the game is over"),
+        }
+    }
+}
+impl std::error::Error for MoveError {}
 
 //==============================================================//
 //  TESTS
@@ -879,35 +898,419 @@ impl std::fmt::Display for ChessPiece {
 #[cfg(test)]
 mod tests {
     use super::*;
+    fn sq(name: &str) -> Square {
+        name.parse().unwrap()
+    }
     #[test]
     fn board_creation() {
         let chessboard = ChessBoard::new();
         // KING
-        let bk = &(chessboard.get(4, 0).unwrap());
+        let bk = &(chessboard.get(sq("e8")).unwrap());
         assert!(bk.piece_color == PieceColor::Black);
         assert!(bk.piece_type == PieceType::King);
-        assert!(Some((4, 0)) == bk.position);
+        assert!(Some(sq("e8")) == bk.position);
         // PAWN
-        let bp = &(chessboard.get(4, 1).unwrap());
+        let bp = &(chessboard.get(sq("e7")).unwrap());
         assert!(bp.piece_color == PieceColor::Black);
         assert!(bp.piece_type == PieceType::Pawn);
-        assert!(Some((4, 1)) == bp.position);
+        assert!(Some(sq("e7")) == bp.position);
     }
     #[test]
 
     fn empty_space_should_not_panic() {
         let chessboard = ChessBoard::new();
-        let empty = chessboard.get(1, 4);
+        let empty = chessboard.get(sq("b4"));
         assert!(empty.is_none());
+        assert!(chessboard.get_possible_moves(sq("b4")).is_empty());
+        assert!(chessboard.get_legal_moves(sq("b4")).is_empty());
+        assert_eq!(chessboard.new_move(sq("b4"), sq("b5"), None), None);
+    }
+    #[test]
+    fn try_make_move_validates() {
+        let mut chessboard = ChessBoard::new();
+        let try_move = |chessboard: &mut ChessBoard, from, to| {
+            chessboard
+                .try_make_move(sq(from), sq(to), None)
+                .map(|o| o.mov)
+        };
+        assert_eq!(
+            try_move(&mut chessboard, "e4", "e5"),
+            Err(MoveError::EmptySquare)
+        );
+        assert_eq!(
+            try_move(&mut chessboard, "e7", "e5"),
+            Err(MoveError::WrongSide)
+        );
+        assert_eq!(
+            try_move(&mut chessboard, "e2", "e5"),
+            Err(MoveError::IllegalMove)
+        );
+        // Capturing one's own piece
+        assert_eq!(
+            try_move(&mut chessboard, "d1", "d2"),
+            Err(MoveError::IllegalMove)
+        );
+        // A promotion piece for a move that doesn't promote
+        assert_eq!(
+            chessboard.try_make_move(sq("e2"), sq("e4"), Some(PieceType::Queen)),
+            Err(MoveError::IllegalMove)
+        );
+        let before = chessboard.clone();
+        let outcome = chessboard.try_make_move(sq("e2"), sq("e4"), None).unwrap();
+        assert_eq!(outcome.mov.kind, MoveKind::DoublePush);
+        assert_eq!(outcome.status, GameStatus::Ongoing);
+        chessboard.unmake_move(outcome.mov, outcome.undo);
+        assert_eq!(chessboard, before);
+
+        let mut chessboard = ChessBoard::from_fen("8/P7/8/8/8/8/8/k1K5 w - - 0 1").unwrap();
+        assert_eq!(
+            chessboard.try_make_move(sq("a7"), sq("a8"), Some(PieceType::King)),
+            Err(MoveError::IllegalMove)
+        );
+        let outcome = chessboard
+            .try_make_move(sq("a7"), sq("a8"), Some(PieceType::Rook))
+            .unwrap();
+        assert_eq!(outcome.mov.promotion, Some(PieceType::Rook));
+
+        let mut chessboard = ChessBoard::new();
+        for (from, to) in &[("f2", "f3"), ("e7", "e5"), ("g2", "g4"), ("d8", "h4")] {
+            assert!(try_move(&mut chessboard, from, to).is_ok());
+        }
+        assert_eq!(
+            try_move(&mut chessboard, "a2", "a3"),
+            Err(MoveError::GameOver)
+        );
     }
     #[test]
     fn move_pawn() {
         let mut chessboard = ChessBoard::new();
-        chessboard.make_move((4, 1), (4, 2));
+        play(&mut chessboard, sq("e2"), sq("e3"));
+        play(&mut chessboard, sq("e7"), sq("e6"));
 
-        let bp = &(chessboard.get(4, 2).unwrap());
+        let bp = &(chessboard.get(sq("e6")).unwrap());
         assert_eq!(bp.piece_color, PieceColor::Black);
         assert_eq!(bp.piece_type, PieceType::Pawn);
-        assert_eq!(Some((4, 2)), bp.position);
+        assert_eq!(Some(sq("e6")), bp.position);
+    }
+    #[test]
+    fn players_alternate() {
+        let mut chessboard = ChessBoard::new();
+        assert_eq!(chessboard.side_to_move, PieceColor::White);
+        assert!(!chessboard.can_move(sq("e7"), sq("e5")));
+        assert!(chessboard.can_move(sq("e2"), sq("e4")));
+        play(&mut chessboard, sq("e2"), sq("e4"));
+
+        assert_eq!(chessboard.side_to_move, PieceColor::Black);
+        assert!(!chessboard.can_move(sq("d2"), sq("d4")));
+        assert!(chessboard.can_move(sq("e7"), sq("e5")));
+    }
+
+    fn remove(chessboard: &mut ChessBoard, square: Square) {
+        chessboard.remove_piece(square);
+    }
+    fn play(chessboard: &mut ChessBoard, from: Square, to: Square) {
+        let mov = chessboard.new_move(from, to, None).unwrap();
+        chessboard.make_move(mov);
+    }
+    /// Destinations of the moves, from a1 to h8
+    fn destinations(moves: Vec<Move>) -> Vec<Square> {
+        let mut squares: Vec<Square> = moves.iter().map(|mov| mov.to).collect();
+        squares.sort();
+        squares
+    }
+    #[test]
+    fn castle_king_side() {
+        let mut chessboard = ChessBoard::new();
+        remove(&mut chessboard, sq("f1"));
+        remove(&mut chessboard, sq("g1"));
+        assert!(chessboard.can_move(sq("e1"), sq("g1")));
+        play(&mut chessboard, sq("e1"), sq("g1"));
+
+        let rook = chessboard.get(sq("f1")).unwrap();
+        assert_eq!(rook.piece_type, PieceType::Rook);
+        assert_eq!(Some(sq("f1")), rook.position);
+        assert!(chessboard.get(sq("h1")).is_none());
+        assert!(!chessboard.castling.white_king_side);
+        assert!(!chessboard.castling.white_queen_side);
+    }
+    #[test]
+    fn castling_rights_lost_after_rook_move() {
+        let mut chessboard = ChessBoard::new();
+        chessboard.side_to_move = PieceColor::Black;
+        remove(&mut chessboard, sq("b8"));
+        remove(&mut chessboard, sq("c8"));
+        remove(&mut chessboard, sq("d8"));
+        assert!(chessboard.can_move(sq("e8"), sq("c8")));
+        play(&mut chessboard, sq("a8"), sq("b8"));
+        play(&mut chessboard, sq("b8"), sq("a8"));
+        assert!(!chessboard.can_move(sq("e8"), sq("c8")));
+        assert!(chessboard.castling.black_king_side);
+    }
+    #[test]
+    fn cannot_castle_through_check() {
+        let mut chessboard = ChessBoard::new();
+        remove(&mut chessboard, sq("f1"));
+        remove(&mut chessboard, sq("g1"));
+        remove(&mut chessboard, sq("f2"));
+        chessboard.side_to_move = PieceColor::Black;
+        play(&mut chessboard, sq("a8"), sq("f5"));
+        assert!(!chessboard.can_move(sq("e1"), sq("g1")));
+    }
+    #[test]
+    fn pawn_double_step() {
+        let mut chessboard = ChessBoard::new();
+        assert_eq!(
+            destinations(chessboard.get_possible_moves(sq("e2"))),
+            vec![sq("e3"), sq("e4")]
+        );
+        assert_eq!(
+            destinations(chessboard.get_possible_moves(sq("d7"))),
+            vec![sq("d5"), sq("d6")]
+        );
+        // Blocked on the second square
+        play(&mut chessboard, sq("d7"), sq("d4"));
+        assert_eq!(
+            destinations(chessboard.get_possible_moves(sq("d2"))),
+            vec![sq("d3")]
+        );
+        // Only allowed from the starting rank
+        play(&mut chessboard, sq("a2"), sq("a3"));
+        assert_eq!(
+            destinations(chessboard.get_possible_moves(sq("a3"))),
+            vec![sq("a4")]
+        );
+    }
+    #[test]
+    fn en_passant_capture() {
+        let mut chessboard = ChessBoard::new();
+        play(&mut chessboard, sq("e2"), sq("e5"));
+        play(&mut chessboard, sq("d7"), sq("d5"));
+        assert_eq!(chessboard.en_passant, Some(sq("d6")));
+        assert!(chessboard.can_move(sq("e5"), sq("d6")));
+        let mov = chessboard.new_move(sq("e5"), sq("d6"), None).unwrap();
+        assert_eq!(mov.kind, MoveKind::EnPassant);
+        assert_eq!(mov.captured, Some(PieceType::Pawn));
+        chessboard.make_move(mov);
+
+        assert!(chessboard.get(sq("d5")).is_none());
+        assert_eq!(chessboard.pieces[11].position, None);
+        assert_eq!(
+            chessboard.get(sq("d6")).unwrap().piece_color,
+            PieceColor::White
+        );
+        assert_eq!(chessboard.en_passant, None);
+    }
+    #[test]
+    fn en_passant_expires() {
+        let mut chessboard = ChessBoard::new();
+        play(&mut chessboard, sq("e2"), sq("e5"));
+        play(&mut chessboard, sq("d7"), sq("d5"));
+        play(&mut chessboard, sq("a2"), sq("a3"));
+        play(&mut chessboard, sq("a7"), sq("a6"));
+        assert!(!chessboard.can_move(sq("e5"), sq("d6")));
+    }
+    #[test]
+    fn pawn_promotion() {
+        let mut chessboard = ChessBoard::new();
+        remove(&mut chessboard, sq("a7"));
+        remove(&mut chessboard, sq("a8"));
+        play(&mut chessboard, sq("a2"), sq("a7"));
+        assert!(chessboard.is_promotion(sq("a7"), sq("a8")));
+        assert!(!chessboard.is_promotion(sq("b2"), sq("b3")));
+        // a8 and b8, four pieces each
+        assert_eq!(chessboard.get_possible_moves(sq("a7")).len(), 8);
+        let mov = chessboard
+            .new_move(sq("a7"), sq("a8"), Some(PieceType::Knight))
+            .unwrap();
+        chessboard.make_move(mov);
+
+        let knight = chessboard.get(sq("a8")).unwrap();
+        assert_eq!(knight.piece_type, PieceType::Knight);
+        assert_eq!(knight.piece_color, PieceColor::White);
+        assert_eq!(
+            destinations(chessboard.get_possible_moves(sq("a8"))),
+            vec![sq("b6"), sq("c7")]
+        );
+    }
+    #[test]
+    fn pinned_piece_cannot_move() {
+        let mut chessboard = ChessBoard::new();
+        // Black queen pins the d2 pawn against the white king
+        remove(&mut chessboard, sq("d1"));
+        remove(&mut chessboard, sq("c2"));
+        chessboard.side_to_move = PieceColor::Black;
+        play(&mut chessboard, sq("d8"), sq("b4"));
+        assert!(!chessboard.get_possible_moves(sq("d2")).is_empty());
+        assert!(chessboard.get_legal_moves(sq("d2")).is_empty());
+        assert!(!chessboard.can_move(sq("d2"), sq("d3")));
+    }
+    #[test]
+    fn king_cannot_walk_into_check() {
+        let mut chessboard = ChessBoard::new();
+        remove(&mut chessboard, sq("e2"));
+        play(&mut chessboard, sq("e1"), sq("e3"));
+        play(&mut chessboard, sq("d8"), sq("d5"));
+        let moves = destinations(chessboard.get_legal_moves(sq("e3")));
+        assert!(!moves.contains(&sq("d4")));
+        assert!(!moves.contains(&sq("e4")));
+        assert!(moves.contains(&sq("f4")));
+        assert!(moves.contains(&sq("e2")));
+    }
+    #[test]
+    fn fools_mate() {
+        let mut chessboard = ChessBoard::new();
+        play(&mut chessboard, sq("f2"), sq("f3"));
+        play(&mut chessboard, sq("e7"), sq("e5"));
+        play(&mut chessboard, sq("g2"), sq("g4"));
+        assert_eq!(chessboard.status(), GameStatus::Ongoing);
+        play(&mut chessboard, sq("d8"), sq("h4"));
+
+        assert!(chessboard.is_in_check(PieceColor::White));
+        assert!(!chessboard.is_in_check(PieceColor::Black));
+        assert_eq!(
+            chessboard.status(),
+            GameStatus::Checkmate(PieceColor::Black)
+        );
+    }
+    #[test]
+    fn stalemate() {
+        let mut chessboard = ChessBoard::new();
+        // Keep only the kings and the white queen
+        for square in Square::all() {
+            if ![sq("e8"), sq("e1"), sq("d1")].contains(&square) {
+                remove(&mut chessboard, square);
+            }
+        }
+        chessboard.side_to_move = PieceColor::Black;
+        play(&mut chessboard, sq("e8"), sq("a8"));
+        play(&mut chessboard, sq("d1"), sq("b6"));
+
+        assert!(!chessboard.is_in_check(PieceColor::Black));
+        assert_eq!(chessboard.status(), GameStatus::Stalemate);
+        chessboard.side_to_move = PieceColor::White;
+        assert_eq!(chessboard.status(), GameStatus::Ongoing);
+    }
+    #[test]
+    fn threefold_repetition() {
+        let mut chessboard = ChessBoard::new();
+        for _ in 0..2 {
+            assert_eq!(chessboard.status(), GameStatus::Ongoing);
+            play(&mut chessboard, sq("g1"), sq("f3"));
+            play(&mut chessboard, sq("g8"), sq("f6"));
+            play(&mut chessboard, sq("f3"), sq("g1"));
+            play(&mut chessboard, sq("f6"), sq("g8"));
+        }
+        assert_eq!(chessboard.repetitions(), 3);
+        assert_eq!(
+            chessboard.status(),
+            GameStatus::Draw(DrawReason::ThreefoldRepetition)
+        );
+
+        // No black pawn can take on e3, so the position after 1.e4 is the
+        // same as after 3.Ng1 and 5.Ng1
+        let mut chessboard = ChessBoard::new();
+        play(&mut chessboard, sq("e2"), sq("e4"));
+        for _ in 0..2 {
+            assert_eq!(chessboard.status(), GameStatus::Ongoing);
+            play(&mut chessboard, sq("g8"), sq("f6"));
+            play(&mut chessboard, sq("g1"), sq("f3"));
+            play(&mut chessboard, sq("f6"), sq("g8"));
+            play(&mut chessboard, sq("f3"), sq("g1"));
+        }
+        assert_eq!(chessboard.repetitions(), 3);
+        assert_eq!(
+            chessboard.status(),
+            GameStatus::Draw(DrawReason::ThreefoldRepetition)
+        );
+    }
+    #[test]
+    fn fifty_move_rule() {
+        let mut chessboard = ChessBoard::new();
+        chessboard.halfmove_clock = 98;
+        play(&mut chessboard, sq("g1"), sq("f3"));
+        assert_eq!(chessboard.status(), GameStatus::Ongoing);
+        play(&mut chessboard, sq("g8"), sq("f6"));
+        assert_eq!(
+            chessboard.status(),
+            GameStatus::Draw(DrawReason::FiftyMoveRule)
+        );
+        // A pawn move resets the clock
+        play(&mut chessboard, sq("e2"), sq("e4"));
+        assert_eq!(chessboard.halfmove_clock, 0);
+    }
+    #[test]
+    fn insufficient_material() {
+        let mut chessboard = ChessBoard::new();
+        // Kings and the light-squared bishops
+        for square in Square::all() {
+            if ![sq("e8"), sq("e1"), sq("f8"), sq("c1")].contains(&square) {
+                remove(&mut chessboard, square);
+            }
+        }
+        assert!(chessboard.is_insufficient_material());
+        assert_eq!(
+            chessboard.status(),
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        );
+        // Bishops on different colors can still mate
+        play(&mut chessboard, sq("c1"), sq("c2"));
+        assert!(!chessboard.is_insufficient_material());
+    }
+    #[test]
+    fn unmake_restores_the_board() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+        // Small xorshift generator, the sequences only need to be reproducible
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize % n
+        };
+        for fen in &fens {
+            for _ in 0..20 {
+                let mut chessboard = ChessBoard::from_fen(fen).unwrap();
+                let mut played = vec![];
+                for _ in 0..40 {
+                    let moves = chessboard.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let mov = moves[random(moves.len())];
+                    let before = chessboard.clone();
+                    let undo = chessboard.make_move(mov);
+                    // The bitboards and the key are updated move by move, never rebuilt
+                    let mut rebuilt = chessboard.clone();
+                    rebuilt.update_bitboards();
+                    rebuilt.hash = rebuilt.position_key();
+                    assert_eq!(rebuilt, chessboard, "{} after {:?}", fen, mov);
+                    played.push((before, mov, undo));
+                }
+                while let Some((before, mov, undo)) = played.pop() {
+                    chessboard.unmake_move(mov, undo);
+                    assert_eq!(chessboard, before, "{} after unmaking {:?}", fen, mov);
+                }
+            }
+        }
+    }
+    #[test]
+    fn legal_moves_play_on_the_board() {
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut chessboard = ChessBoard::from_fen(kiwipete).unwrap();
+        let before = chessboard.clone();
+        let expected: Vec<Move> = Square::all()
+            .flat_map(|square| chessboard.get_legal_moves(square))
+            .filter(|mov| chessboard.get(mov.from).unwrap().piece_color == PieceColor::White)
+            .collect();
+        let moves = chessboard.legal_moves();
+        assert_eq!(chessboard, before);
+        assert_eq!(moves.len(), 48);
+        assert_eq!(moves.len(), expected.len());
+        assert!(moves.iter().all(|mov| expected.contains(mov)));
     }
 }
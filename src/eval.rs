@@ -0,0 +1,394 @@
+//==============================================================//
+//  EVALUATION
+//==============================================================//
+use crate::bitboard::*;
+use crate::board::*;
+use crate::minimax::points;
+use crate::pst::*;
+use crate::square::*;
+
+const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+const TERM_COUNT: usize = 10;
+
+/// Static evaluation the search uses to score quiet positions
+pub trait Evaluator {
+    /// Score of the position in centipawns for the side to move
+    fn evaluate(&self, board: &ChessBoard) -> i32;
+}
+
+/// Counts the material only
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Material;
+
+/// Material and placement of the pieces from the piece-square tables
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PieceSquares;
+
+/// Centipawns given for each unit a term counts, evaluates with every term
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    pub doubled_pawn: i32,
+    pub isolated_pawn: i32,
+    /// Per rank a passed pawn has advanced
+    pub passed_pawn: i32,
+    pub rook_open_file: i32,
+    pub rook_half_open_file: i32,
+    pub bishop_pair: i32,
+    /// Per square a knight, bishop, rook or queen can move to
+    pub mobility: i32,
+    /// Per pawn in front of the king, fading out towards the endgame
+    pub king_shelter: i32,
+    /// Per square next to the enemy king that is attacked, fading out
+    /// towards the endgame
+    pub king_attack: i32,
+}
+
+/// One term of the evaluation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TermScore {
+    pub name: &'static str,
+    /// What the term counts for each side
+    pub white: i32,
+    pub black: i32,
+    /// Weighted difference in centipawns, from White's point of view
+    pub score: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            doubled_pawn: -10,
+            isolated_pawn: -15,
+            passed_pawn: 10,
+            rook_open_file: 20,
+            rook_half_open_file: 10,
+            bishop_pair: 30,
+            mobility: 3,
+            king_shelter: 10,
+            king_attack: 8,
+        }
+    }
+}
+
+impl std::fmt::Display for TermScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} / {} = {}",
+            self.name, self.white, self.black, self.score
+        )
+    }
+}
+
+impl Evaluator for Material {
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        let material = |color| -> i32 {
+            PIECE_TYPES
+                .iter()
+                .map(|piece_type| {
+                    let count = board.pieces_of(color, *piece_type).count_ones() as i32;
+                    count * points(*piece_type) * 100
+                })
+                .sum()
+        };
+        for_side_to_move(
+            board,
+            material(PieceColor::White) - material(PieceColor::Black),
+        )
+    }
+}
+
+impl Evaluator for PieceSquares {
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        let score =
+            piece_squares(board, PieceColor::White) - piece_squares(board, PieceColor::Black);
+        for_side_to_move(board, score)
+    }
+}
+
+impl Evaluator for Weights {
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        let score = terms(board, self).iter().map(|term| term.score).sum();
+        for_side_to_move(board, score)
+    }
+}
+
+/// Turns a score for White into one for the side to move
+fn for_side_to_move(board: &ChessBoard, score: i32) -> i32 {
+    match board.side_to_move {
+        PieceColor::White => score,
+        PieceColor::Black => -score,
+    }
+}
+
+/// Every term of the evaluation, they add up to the score for White. Only
+/// used when debugging the weights, the app never calls it.
+#[allow(dead_code)]
+pub fn explain_eval(board: &ChessBoard, weights: &Weights) -> Vec<TermScore> {
+    terms(board, weights).to_vec()
+}
+
+fn terms(board: &ChessBoard, weights: &Weights) -> [TermScore; TERM_COUNT] {
+    let phase = phase(board);
+    let term = |name, weight: i32, count: fn(&ChessBoard, PieceColor) -> i32| {
+        let white = count(board, PieceColor::White);
+        let black = count(board, PieceColor::Black);
+        TermScore {
+            name,
+            white,
+            black,
+            score: weight * (white - black),
+        }
+    };
+    // King safety matters less with fewer pieces left to attack it
+    let tapered = |mut term: TermScore| {
+        term.score = term.score * phase / MAX_PHASE;
+        term
+    };
+    [
+        term("pieces", 1, piece_squares),
+        term("doubled pawns", weights.doubled_pawn, doubled_pawns),
+        term("isolated pawns", weights.isolated_pawn, isolated_pawns),
+        term("passed pawns", weights.passed_pawn, passed_pawns),
+        term(
+            "rooks on open files",
+            weights.rook_open_file,
+            rooks_on_open_files,
+        ),
+        term(
+            "rooks on half-open files",
+            weights.rook_half_open_file,
+            rooks_on_half_open_files,
+        ),
+        term("bishop pair", weights.bishop_pair, bishop_pair),
+        term("mobility", weights.mobility, mobility),
+        tapered(term("king shelter", weights.king_shelter, king_shelter)),
+        tapered(term("king attack", weights.king_attack, king_attack)),
+    ]
+}
+
+//==============================================================//
+//  TERMS
+//==============================================================//
+
+/// Pawns on a file beyond the first one
+fn doubled_pawns(board: &ChessBoard, color: PieceColor) -> i32 {
+    let pawns = board.pieces_of(color, PieceType::Pawn);
+    (0..8)
+        .map(|file| (pawns & file_mask(file)).count_ones().saturating_sub(1) as i32)
+        .sum()
+}
+
+/// Pawns without a pawn of their color on the files next to them
+fn isolated_pawns(board: &ChessBoard, color: PieceColor) -> i32 {
+    let pawns = board.pieces_of(color, PieceType::Pawn);
+    squares(pawns)
+        .filter(|square| pawns & adjacent_files(square.file()) == 0)
+        .count() as i32
+}
+
+/// Pawns no enemy pawn can stop or take, each counted by how many ranks it
+/// has advanced from the first rank
+fn passed_pawns(board: &ChessBoard, color: PieceColor) -> i32 {
+    let enemy_pawns = board.pieces_of(color.opponent(), PieceType::Pawn);
+    squares(board.pieces_of(color, PieceType::Pawn))
+        .filter(|square| {
+            let files = file_mask(square.file()) | adjacent_files(square.file());
+            enemy_pawns & files & ranks_ahead(color, square.rank()) == 0
+        })
+        .map(|square| relative_rank(color, square) as i32)
+        .sum()
+}
+
+/// Rooks on a file without pawns
+fn rooks_on_open_files(board: &ChessBoard, color: PieceColor) -> i32 {
+    let pawns = board.by_type[PieceType::Pawn.index()];
+    squares(board.pieces_of(color, PieceType::Rook))
+        .filter(|square| pawns & file_mask(square.file()) == 0)
+        .count() as i32
+}
+
+/// Rooks on a file with only enemy pawns
+fn rooks_on_half_open_files(board: &ChessBoard, color: PieceColor) -> i32 {
+    let own_pawns = board.pieces_of(color, PieceType::Pawn);
+    let enemy_pawns = board.pieces_of(color.opponent(), PieceType::Pawn);
+    squares(board.pieces_of(color, PieceType::Rook))
+        .filter(|square| {
+            let file = file_mask(square.file());
+            own_pawns & file == 0 && enemy_pawns & file != 0
+        })
+        .count() as i32
+}
+
+/// 1 with two bishops or more
+fn bishop_pair(board: &ChessBoard, color: PieceColor) -> i32 {
+    (board.pieces_of(color, PieceType::Bishop).count_ones() >= 2) as i32
+}
+
+/// Squares the knights, bishops, rooks and queens attack that aren't taken
+/// by a piece of their color
+fn mobility(board: &ChessBoard, color: PieceColor) -> i32 {
+    let own = board.by_color[color.index()];
+    let occupied = board.occupied();
+    let mut moves = 0;
+    for piece_type in &[
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        for square in squares(board.pieces_of(color, *piece_type)) {
+            let attacks = match piece_type {
+                PieceType::Knight => KNIGHT_ATTACKS[square.index()],
+                PieceType::Bishop => bishop_attacks(square, occupied),
+                PieceType::Rook => rook_attacks(square, occupied),
+                _ => queen_attacks(square, occupied),
+            };
+            moves += (attacks & !own).count_ones();
+        }
+    }
+    moves as i32
+}
+
+/// Pawns of their color on the two ranks in front of the king, on its file
+/// and the files next to it
+fn king_shelter(board: &ChessBoard, color: PieceColor) -> i32 {
+    let king = match board.king_position(color) {
+        Some(king) => king,
+        None => return 0,
+    };
+    let forward = match color {
+        PieceColor::White => 1,
+        PieceColor::Black => -1,
+    };
+    let ranks = (1..=2)
+        .filter_map(|step| king.offset(0, forward * step))
+        .fold(0, |ranks, square| ranks | rank_mask(square.rank()));
+    let files = file_mask(king.file()) | adjacent_files(king.file());
+    (board.pieces_of(color, PieceType::Pawn) & ranks & files).count_ones() as i32
+}
+
+/// Squares next to the enemy king that `color` attacks
+fn king_attack(board: &ChessBoard, color: PieceColor) -> i32 {
+    match board.king_position(color.opponent()) {
+        Some(king) => squares(KING_ATTACKS[king.index()])
+            .filter(|square| board.is_square_attacked(*square, color))
+            .count() as i32,
+        None => 0,
+    }
+}
+
+fn file_mask(file: usize) -> Bitboard {
+    FILE_A << file
+}
+
+fn rank_mask(rank: usize) -> Bitboard {
+    0xff << (8 * rank)
+}
+
+fn adjacent_files(file: usize) -> Bitboard {
+    let left = if file > 0 { file_mask(file - 1) } else { 0 };
+    let right = if file < 7 { file_mask(file + 1) } else { 0 };
+    left | right
+}
+
+/// Ranks in front of `rank` from the side of `color`
+fn ranks_ahead(color: PieceColor, rank: usize) -> Bitboard {
+    match color {
+        PieceColor::White if rank >= 7 => 0,
+        PieceColor::White => !0 << (8 * (rank + 1)),
+        PieceColor::Black => (1 << (8 * rank)) - 1,
+    }
+}
+
+/// Rank counted from the side of `color`, 0 is its first rank
+fn relative_rank(color: PieceColor, square: Square) -> usize {
+    match color {
+        PieceColor::White => square.rank(),
+        PieceColor::Black => 7 - square.rank(),
+    }
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn board(fen: &str) -> ChessBoard {
+        ChessBoard::from_fen(fen).unwrap()
+    }
+    fn counts(fen: &str, count: fn(&ChessBoard, PieceColor) -> i32) -> (i32, i32) {
+        let board = board(fen);
+        (
+            count(&board, PieceColor::White),
+            count(&board, PieceColor::Black),
+        )
+    }
+    #[test]
+    fn pawn_structure() {
+        // White: doubled pawns on the c file held back by b7, a passed pawn
+        // on e6. Black: a passed pawn on h5. They are all isolated.
+        let fen = "4k3/1p6/4P3/7p/8/2P5/2P5/4K3 w - - 0 1";
+        assert_eq!(counts(fen, doubled_pawns), (1, 0));
+        assert_eq!(counts(fen, isolated_pawns), (3, 2));
+        assert_eq!(counts(fen, passed_pawns), (5, 3));
+        let fen = "4k3/3p4/8/4P3/8/8/8/4K3 w - - 0 1";
+        assert_eq!(counts(fen, passed_pawns), (0, 0));
+    }
+    #[test]
+    fn pieces() {
+        let fen = "rr2k3/1p6/8/8/8/8/PP6/2R1KR2 w - - 0 1";
+        assert_eq!(counts(fen, rooks_on_open_files), (2, 0));
+        assert_eq!(counts(fen, rooks_on_half_open_files), (0, 1));
+        assert_eq!(
+            counts("2b1kb2/8/8/8/8/8/8/2B1K1N1 w - - 0 1", bishop_pair),
+            (0, 1)
+        );
+        // A knight in the corner, a rook blocked by its king
+        let fen = "4k3/8/8/8/8/8/8/N3K2R w - - 0 1";
+        assert_eq!(counts(fen, mobility), (2 + 9, 0));
+    }
+    #[test]
+    fn king_safety() {
+        let fen = "6k1/5p1p/8/8/8/3n4/PPP2q2/1K6 w - - 0 1";
+        assert_eq!(counts(fen, king_shelter), (3, 2));
+        // The queen attacks c2, the knight b2 and c1
+        assert_eq!(counts(fen, king_attack), (0, 3));
+    }
+    #[test]
+    fn terms_add_up_to_the_score() {
+        let board = board("r1bqk2r/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP2BPPP/R2QKB1R b KQkq - 0 1");
+        let weights = Weights::default();
+        let terms = explain_eval(&board, &weights);
+        assert_eq!(terms.len(), TERM_COUNT);
+        let total: i32 = terms.iter().map(|term| term.score).sum();
+        assert_eq!(weights.evaluate(&board), -total);
+        // Without weights only the pieces count
+        let none = Weights {
+            doubled_pawn: 0,
+            isolated_pawn: 0,
+            passed_pawn: 0,
+            rook_open_file: 0,
+            rook_half_open_file: 0,
+            bishop_pair: 0,
+            mobility: 0,
+            king_shelter: 0,
+            king_attack: 0,
+        };
+        assert_eq!(none.evaluate(&board), -terms[0].score);
+        assert_eq!(weights.evaluate(&ChessBoard::new()), 0);
+    }
+    #[test]
+    fn evaluators_agree_on_material() {
+        let board = board("4k3/8/8/8/8/8/8/3QK3 b - - 0 1");
+        assert_eq!(Material.evaluate(&board), -900);
+        assert_eq!(Material.evaluate(&ChessBoard::new()), 0);
+        let evaluators: [&dyn Evaluator; 3] = [&Material, &PieceSquares, &Weights::default()];
+        for evaluator in &evaluators {
+            assert!(evaluator.evaluate(&board) < -800);
+            assert_eq!(evaluator.evaluate(&ChessBoard::new()), 0);
+        }
+    }
+}
@@ -0,0 +1,441 @@
+//==============================================================//
+//  FEN IMPORT AND EXPORT
+//==============================================================//
+use crate::board::*;
+use crate::square::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// A FEN has 6 space separated fields (the move counters may be omitted)
+    WrongFieldCount(usize),
+    WrongRankCount(usize),
+    /// The rank (8 to 1) doesn't describe exactly 8 squares
+    WrongRankLength(usize),
+    InvalidPiece(char),
+    TooManyPieces,
+    /// Each side needs exactly one king
+    InvalidKingCount(PieceColor),
+    InvalidSideToMove(String),
+    /// Pawns can't stand on the first or last rank
+    PawnOnBackRank(Square),
+    /// The side that just moved can't have left its king in check
+    OpponentInCheck,
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::WrongFieldCount(n) => write!(f, "expected 6 fields, found {}", n),
+            FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            FenError::WrongRankLength(rank) => {
+                write!(f, "rank {} doesn't have exactly 8 squares", rank)
+            }
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::TooManyPieces => write!(f, "more than 32 pieces on the board"),
+            FenError::InvalidKingCount(color) => write!(
+                f,
+                "{} must have exactly one king",
+                match color {
+                    PieceColor::White => "white",
+                    PieceColor::Black => "black",
+                }
+            ),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::PawnOnBackRank(square) => write!(f, "pawn on back rank square {}", square),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl ChessBoard {
+    /// Parses a position in Forsyth-Edwards Notation
+    pub fn from_fen(fen: &str) -> Result<ChessBoard, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 6 && fields.len() != 4 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+        let mut cb = ChessBoard::new();
+        cb.board = [None; 64];
+        for piece in cb.pieces.iter_mut() {
+            piece.position = None;
+        }
+
+        // PIECE PLACEMENT
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        let mut count = 0;
+        for (y, rank) in ranks.iter().enumerate() {
+            let mut x = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                    x += empty as usize;
+                    continue;
+                }
+                let (piece_color, piece_type) =
+                    piece_from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                if x >= 8 {
+                    return Err(FenError::WrongRankLength(8 - y));
+                }
+                if count == cb.pieces.len() {
+                    return Err(FenError::TooManyPieces);
+                }
+                cb.pieces[count] = ChessPiece {
+                    piece_color,
+                    piece_type,
+                    id: count + 1,
+                    position: Some(Square::new(x, 7 - y)),
+                };
+                cb.board[Square::new(x, 7 - y).index()] = Some(count);
+                count += 1;
+                x += 1;
+            }
+            if x != 8 {
+                return Err(FenError::WrongRankLength(8 - y));
+            }
+        }
+        cb.update_bitboards();
+        for color in &[PieceColor::White, PieceColor::Black] {
+            let kings = cb
+                .pieces
+                .iter()
+                .filter(|p| {
+                    p.position.is_some()
+                        && p.piece_type == PieceType::King
+                        && p.piece_color == *color
+                })
+                .count();
+            if kings != 1 {
+                return Err(FenError::InvalidKingCount(*color));
+            }
+        }
+
+        // SIDE TO MOVE
+        cb.side_to_move = match fields[1] {
+            "w" => PieceColor::White,
+            "b" => PieceColor::Black,
+            s => return Err(FenError::InvalidSideToMove(s.to_string())),
+        };
+        if let Some(square) = cb
+            .pieces
+            .iter()
+            .filter(|p| p.piece_type == PieceType::Pawn)
+            .filter_map(|p| p.position)
+            .find(|square| square.rank() == 0 || square.rank() == 7)
+        {
+            return Err(FenError::PawnOnBackRank(square));
+        }
+        if cb.is_in_check(cb.side_to_move.opponent()) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        // CASTLING
+        let invalid_castling = || FenError::InvalidCastling(fields[2].to_string());
+        cb.castling = CastlingRights {
+            white_king_side: false,
+            white_queen_side: false,
+            black_king_side: false,
+            black_queen_side: false,
+        };
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                // The king and the rook must still be on their starting squares
+                let (right, king, rook) = match c {
+                    'K' => (&mut cb.castling.white_king_side, (4, 0), (7, 0)),
+                    'Q' => (&mut cb.castling.white_queen_side, (4, 0), (0, 0)),
+                    'k' => (&mut cb.castling.black_king_side, (4, 7), (7, 7)),
+                    'q' => (&mut cb.castling.black_queen_side, (4, 7), (0, 7)),
+                    _ => return Err(invalid_castling()),
+                };
+                if *right {
+                    return Err(invalid_castling());
+                }
+                *right = true;
+                let color = if c.is_ascii_uppercase() {
+                    PieceColor::White
+                } else {
+                    PieceColor::Black
+                };
+                let in_place = |(file, rank), piece_type: PieceType| match cb.board
+                    [Square::new(file, rank).index()]
+                {
+                    Some(i) => {
+                        cb.pieces[i].piece_type == piece_type && cb.pieces[i].piece_color == color
+                    }
+                    None => false,
+                };
+                if !in_place(king, PieceType::King) || !in_place(rook, PieceType::Rook) {
+                    return Err(invalid_castling());
+                }
+            }
+        }
+
+        // EN PASSANT
+        cb.en_passant = match fields[3] {
+            "-" => None,
+            s => {
+                // Behind a pawn that just advanced two squares, which it
+                // skipped and left empty
+                let (expected_rank, forward) = match cb.side_to_move {
+                    PieceColor::White => (5, -1),
+                    PieceColor::Black => (2, 1),
+                };
+                let empty = |square: Option<Square>| square.is_some_and(|s| cb.get(s).is_none());
+                let advanced = |square: Square| {
+                    empty(Some(square))
+                        && empty(square.offset(0, -forward))
+                        && square
+                            .offset(0, forward)
+                            .and_then(|s| cb.get(s))
+                            .is_some_and(|p| {
+                                p.piece_type == PieceType::Pawn
+                                    && p.piece_color == cb.side_to_move.opponent()
+                            })
+                };
+                match s.parse::<Square>() {
+                    Ok(square) if square.rank() == expected_rank && advanced(square) => {
+                        Some(square)
+                    }
+                    _ => return Err(FenError::InvalidEnPassant(s.to_string())),
+                }
+            }
+        };
+
+        // MOVE COUNTERS
+        if fields.len() == 6 {
+            cb.halfmove_clock = fields[4]
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(fields[4].to_string()))?;
+            cb.fullmove_number = fields[5]
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| FenError::InvalidFullmoveNumber(fields[5].to_string()))?;
+        } else {
+            cb.halfmove_clock = 0;
+            cb.fullmove_number = 1;
+        }
+
+        cb.hash = cb.position_key();
+        cb.history = vec![cb.hash];
+        Ok(cb)
+    }
+
+    /// Describes the position in Forsyth-Edwards Notation
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.get(Square::new(file, rank)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement += &empty.to_string();
+                            empty = 0;
+                        }
+                        placement.push(piece_to_char(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement += &empty.to_string();
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let mut castling = String::new();
+        if self.castling.white_king_side {
+            castling.push('K');
+        }
+        if self.castling.white_queen_side {
+            castling.push('Q');
+        }
+        if self.castling.black_king_side {
+            castling.push('k');
+        }
+        if self.castling.black_queen_side {
+            castling.push('q');
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            match self.side_to_move {
+                PieceColor::White => "w",
+                PieceColor::Black => "b",
+            },
+            castling,
+            self.en_passant
+                .map(|square| square.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+}
+
+fn piece_from_char(c: char) -> Option<(PieceColor, PieceType)> {
+    let color = if c.is_ascii_uppercase() {
+        PieceColor::White
+    } else {
+        PieceColor::Black
+    };
+    let piece_type = match c.to_ascii_lowercase() {
+        'p' => PieceType::Pawn,
+        'n' => PieceType::Knight,
+        'b' => PieceType::Bishop,
+        'r' => PieceType::Rook,
+        'q' => PieceType::Queen,
+        'k' => PieceType::King,
+        _ => return None,
+    };
+    Some((color, piece_type))
+}
+
+fn piece_to_char(piece: &ChessPiece) -> char {
+    let c = match piece.piece_type {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+    match piece.piece_color {
+        PieceColor::White => c.to_ascii_uppercase(),
+        PieceColor::Black => c,
+    }
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    fn sq(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+    #[test]
+    fn start_position() {
+        assert_eq!(ChessBoard::new().to_fen(), START_FEN);
+        let chessboard = ChessBoard::from_fen(START_FEN).unwrap();
+        assert_eq!(chessboard.to_string(), ChessBoard::new().to_string());
+        assert_eq!(chessboard.position_key(), ChessBoard::new().position_key());
+    }
+    #[test]
+    fn round_trip() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkb1r/pp1p1ppp/5n2/2pPp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 4",
+            "4k3/8/8/8/8/8/8/4K3 b - - 37 90",
+        ];
+        for fen in &fens {
+            assert_eq!(&ChessBoard::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+    #[test]
+    fn moves_update_fen() {
+        let mut chessboard = ChessBoard::new();
+        let mov = chessboard.new_move(sq("e2"), sq("e4"), None).unwrap();
+        chessboard.make_move(mov);
+        assert_eq!(
+            chessboard.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        let mov = chessboard.new_move(sq("g8"), sq("f6"), None).unwrap();
+        chessboard.make_move(mov);
+        assert_eq!(
+            chessboard.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"
+        );
+    }
+    #[test]
+    fn counters_are_optional() {
+        let chessboard = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(chessboard.halfmove_clock, 0);
+        assert_eq!(chessboard.fullmove_number, 1);
+    }
+    #[test]
+    fn malformed_fen() {
+        let cases = [
+            ("8/8/8/8/8/8/8/8 w - -  0", FenError::WrongFieldCount(5)),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::WrongRankCount(7)),
+            ("4k3/9/8/8/8/8/8/4K3 w - - 0 1", FenError::InvalidPiece('9')),
+            (
+                "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+                FenError::WrongRankLength(1),
+            ),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::InvalidPiece('X')),
+            (
+                "4k3/8/8/8/8/8/8/8 w - - 0 1",
+                FenError::InvalidKingCount(PieceColor::White),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 white - - 0 1",
+                FenError::InvalidSideToMove("white".to_string()),
+            ),
+            (
+                "4k2P/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::PawnOnBackRank(Square::new(7, 7)),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/p3K3 b - - 0 1",
+                FenError::PawnOnBackRank(Square::new(0, 0)),
+            ),
+            ("4k3/8/8/8/8/8/4R3/4K3 w - - 0 1", FenError::OpponentInCheck),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                FenError::InvalidCastling("K".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e4 0 1",
+                FenError::InvalidEnPassant("e4".to_string()),
+            ),
+            (
+                "K7/8/8/8/8/8/8/7k w - e6 0 1",
+                FenError::InvalidEnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/4p3/8/4p3/8/8/8/4K3 w - e6 0 1",
+                FenError::InvalidEnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::InvalidHalfmoveClock("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+        ];
+        for (fen, error) in &cases {
+            assert_eq!(
+                ChessBoard::from_fen(fen).err().as_ref(),
+                Some(error),
+                "{}",
+                fen
+            );
+        }
+    }
+}
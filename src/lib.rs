@@ -1,19 +1,47 @@
 #![allow(clippy::wildcard_imports)]
 #![allow(unused_imports)] // TODO: Remove
+mod bitboard;
 mod board;
+mod eval;
+mod fen;
 mod minimax;
+mod perft;
+mod pgn;
+mod pst;
+mod san;
+mod square;
+mod transposition;
+mod zobrist;
 use board::*;
+use eval::*;
 use minimax::*;
+use pgn::*;
 use seed::{prelude::*, *};
+use square::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 // ------ ------
 //     Init
 // ------ ------
 
 // `init` describes what should happen when your app started.
 fn init(_: Url, _: &mut impl Orders<Msg>) -> Model {
+    let board = ChessBoard::new();
     Model {
-        board: Default::default(),
+        record: new_record(&board, PieceColor::White),
+        board,
         selected: None,
+        promotion: None,
+        status: GameStatus::Ongoing,
+        player: PieceColor::White,
+        think_time: 1000,
+        evaluator: EvaluatorKind::Full,
+        search: Rc::new(RefCell::new(Search::new(
+            SearchLimits::default(),
+            TABLE_SIZE,
+            EvaluatorKind::Full,
+        ))),
+        error: None,
     }
 }
 #[wasm_bindgen]
@@ -34,6 +62,9 @@ extern "C" {
     fn log_many(a: &str, b: &str);
 }
 
+/// Megabytes of memory for the AI's transposition table
+const TABLE_SIZE: usize = 16;
+
 // ------ ------
 //     Model
 // ------ ------
@@ -41,66 +72,236 @@ extern "C" {
 // `Model` describes our app state.
 struct Model {
     board: ChessBoard,
-    selected: Option<(usize, usize)>,
+    /// Moves played since the game started
+    record: GameRecord,
+    selected: Option<Square>,
+    /// Promotion move waiting for the player to pick a piece
+    promotion: Option<Move>,
+    status: GameStatus,
+    /// Color played by the user, the AI plays the other one
+    player: PieceColor,
+    /// Milliseconds the AI spends on each move
+    think_time: u32,
+    /// How the AI scores positions
+    evaluator: EvaluatorKind,
+    /// The AI's search, its transposition table is kept from one move to the
+    /// next until another game starts
+    search: Rc<RefCell<Search<EvaluatorKind>>>,
+    /// Problem with the last FEN or PGN the user loaded
+    error: Option<String>,
+}
+
+/// Evaluations the AI can search with
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EvaluatorKind {
+    Material,
+    PieceSquares,
+    Full,
+}
+
+impl EvaluatorKind {
+    fn name(self) -> &'static str {
+        match self {
+            EvaluatorKind::Material => "Material",
+            EvaluatorKind::PieceSquares => "Piece tables",
+            EvaluatorKind::Full => "Full evaluation",
+        }
+    }
+}
+
+impl Evaluator for EvaluatorKind {
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        match self {
+            EvaluatorKind::Material => Material.evaluate(board),
+            EvaluatorKind::PieceSquares => PieceSquares.evaluate(board),
+            EvaluatorKind::Full => Weights::default().evaluate(board),
+        }
+    }
 }
 
 // ------ ------
 //    Update
 // ------ ------
 
-// (Remove the line below once any of your `Msg` variants doesn't implement `Copy`.)
-#[derive(Copy, Clone)]
 // `Msg` describes the different events you can modify state with.
 enum Msg {
-    Select(usize, usize),
-    EnemyMove(usize, usize, usize, usize),
+    Select(Square),
+    Promote(PieceType),
+    EnemyMove(Move),
+    NewGame(PieceColor),
+    LoadFen(String),
+    LoadPgn(String),
+    ThinkTime(u32),
+    Evaluator(EvaluatorKind),
 }
 
 // `update` describes how to handle each `Msg`.
 fn update(msg: Msg, model: &mut Model, o: &mut impl Orders<Msg>) {
     match msg {
-        Msg::Select(x, y) => {
-            if let Some((x1, y1)) = model.selected {
-                if (x1, y1) == (x, y) {
+        Msg::Select(square) => {
+            if model.status != GameStatus::Ongoing || model.board.side_to_move != model.player {
+                return;
+            }
+            model.promotion = None;
+            if let Some(from) = model.selected {
+                if from == square {
                     model.selected = None;
                     return;
                 }
-                let piece = model.board.get(x1, y1);
+                let piece = model.board.get(from);
                 if let Some(_p) = piece {
                     /* if p.can_move(x, y) {
                         model.board.make_move((x1, y1), (x, y));
                         model.selected = None;
                         return;
                     } */
-                    if model.board.can_move(x1, y1, x, y) {
-                        model.board.make_move((x1, y1), (x, y));
+                    if model.board.can_move(from, square) {
                         model.selected = None;
-                        o.perform_cmd({
-                            let clonedb= model.board.clone();
-                            async {
-                                let mov = maximize(clonedb, 0);
-                                Msg::EnemyMove(mov.start.0, mov.start.1, mov.end.0, mov.end.1)
-                            }
-                        });
+                        if model.board.is_promotion(from, square) {
+                            // Wait for the player to pick the new piece
+                            model.promotion = model.board.new_move(from, square, None);
+                        } else if play_move(model, from, square, None) {
+                            play_enemy_move(model, o);
+                        }
                         return;
                     }
                 }
             }
-            let piece = model.board.get(x, y);
-            if let Some(ChessPiece {
-                piece_color: PieceColor::White,
-                ..
-            }) = piece
-            {
-                model.selected = Some((x, y));
+            let piece = model.board.get(square);
+            if let Some(p) = piece {
+                if p.piece_color == model.player {
+                    model.selected = Some(square);
+                }
+            }
+        }
+        Msg::Promote(piece_type) => {
+            if let Some(mov) = model.promotion.take() {
+                if play_move(model, mov.from, mov.to, Some(piece_type)) {
+                    play_enemy_move(model, o);
+                }
+            }
+        }
+        Msg::EnemyMove(mov) => {
+            play_move(model, mov.from, mov.to, mov.promotion);
+        }
+        Msg::NewGame(player) => {
+            model.board = ChessBoard::new();
+            model.record = new_record(&model.board, player);
+            model.selected = None;
+            model.promotion = None;
+            model.status = GameStatus::Ongoing;
+            model.player = player;
+            model.error = None;
+            model.search.borrow_mut().clear_table();
+            if player == PieceColor::Black {
+                play_enemy_move(model, o);
+            }
+        }
+        Msg::LoadFen(fen) => match ChessBoard::from_fen(&fen) {
+            Ok(board) => {
+                model.record = new_record(&board, model.player);
+                model.board = board;
+                model.selected = None;
+                model.promotion = None;
+                model.error = None;
+                model.search.borrow_mut().clear_table();
+                model.status = model.board.status();
+                if model.status == GameStatus::Ongoing && model.board.side_to_move != model.player {
+                    play_enemy_move(model, o);
+                }
             }
+            Err(error) => model.error = Some(format!("Invalid FEN: {}", error)),
+        },
+        Msg::LoadPgn(pgn) => match read_pgn(&pgn) {
+            Ok(games) => match games.into_iter().next() {
+                // Continue the first game from its last position
+                Some(record) => {
+                    model.board = record.position_after(record.moves.len());
+                    model.status = record.status;
+                    model.record = record;
+                    model.selected = None;
+                    model.promotion = None;
+                    model.error = None;
+                    model.search.borrow_mut().clear_table();
+                    if model.status == GameStatus::Ongoing
+                        && model.board.side_to_move != model.player
+                    {
+                        play_enemy_move(model, o);
+                    }
+                }
+                None => model.error = Some("Invalid PGN: no games found".to_string()),
+            },
+            Err(error) => model.error = Some(format!("Invalid PGN: {}", error)),
+        },
+        Msg::ThinkTime(think_time) => model.think_time = think_time,
+        Msg::Evaluator(evaluator) => {
+            model.evaluator = evaluator;
+            model.search.borrow_mut().set_evaluator(evaluator);
+        }
+    }
+}
+
+/// Plays a move on the board and adds it to the game record, false when the
+/// move was refused
+fn play_move(model: &mut Model, from: Square, to: Square, promotion: Option<PieceType>) -> bool {
+    let before = model.board.clone();
+    match model.board.try_make_move(from, to, promotion) {
+        Ok(outcome) => {
+            model.record.push(&before, outcome.mov);
+            model.status = outcome.status;
+            true
         }
-        Msg::EnemyMove(x0, y0, x1, y1) => {
-            model.board.make_move((x0, y0), (x1, y1));
+        Err(error) => {
+            log(&format!("Refused move {}{}: {}", from, to, error));
+            false
         }
     }
 }
 
+fn new_record(board: &ChessBoard, player: PieceColor) -> GameRecord {
+    let mut record = GameRecord::new(board);
+    let date = js_sys::Date::new_0();
+    record.set_tag("Event", "Casual game");
+    record.set_tag("Site", "ChessRS");
+    record.set_tag(
+        "Date",
+        &format!(
+            "{:04}.{:02}.{:02}",
+            date.get_full_year(),
+            date.get_month() + 1,
+            date.get_date()
+        ),
+    );
+    let (white, black) = match player {
+        PieceColor::White => ("Player", "ChessRS AI"),
+        PieceColor::Black => ("ChessRS AI", "Player"),
+    };
+    record.set_tag("White", white);
+    record.set_tag("Black", black);
+    record
+}
+
+fn play_enemy_move(model: &mut Model, o: &mut impl Orders<Msg>) {
+    if model.status != GameStatus::Ongoing {
+        return;
+    }
+    o.perform_cmd({
+        let mut clonedb = model.board.clone();
+        let limits = SearchLimits::time(model.think_time);
+        let search = Rc::clone(&model.search);
+        async move {
+            let mut search = search.borrow_mut();
+            search.limits = limits;
+            let best_move = search.best_move(&mut clonedb);
+            log(&format!(
+                "Searched {} positions, {} plies deep",
+                search.nodes, search.depth
+            ));
+            best_move.mov.map(Msg::EnemyMove)
+        }
+    });
+}
+
 // ------ ------
 //     View
 // ------ ------
@@ -109,6 +310,7 @@ fn update(msg: Msg, model: &mut Model, o: &mut impl Orders<Msg>) {
 #[allow(clippy::trivially_copy_pass_by_ref)]
 // `view` describes what to display.
 fn view(model: &Model) -> Node<Msg> {
+    let player = model.player;
     div![
         C!["container"],
         table![
@@ -116,24 +318,22 @@ fn view(model: &Model) -> Node<Msg> {
             // Draw checkboard
             (0..8).map(|i| {
                 tr![(0..8).map(|j| {
+                    let square = square_at(player, j, i);
                     td![
                         C![if (i + j) % 2 == 0 { "white" } else { "black" }],
-                        C![if let Some((x, y)) = model.selected {
-                            if x == j && y == i {
-                                "selected"
-                            } else {
-                                ""
-                            }
+                        C![if model.selected == Some(square) {
+                            "selected"
                         } else {
                             ""
                         },],
-                        ev(Ev::Click, move |_| Msg::Select(j, i))
+                        ev(Ev::Click, move |_| Msg::Select(square))
                     ]
                 })]
             }),
         ],
         model.board.pieces.iter().map(|p| {
-            if p.position.is_some() {
+            if let Some(square) = p.position {
+                let (left, top) = screen_position(player, square);
                 div![
                     C!["piece"],
                     img![attrs! {
@@ -141,17 +341,163 @@ fn view(model: &Model) -> Node<Msg> {
                     }],
                     style![
                         St::Position => "absolute",
-                        St::Top => format!("{}rem", p.position.unwrap().1*5),
-                        St::Left => format!("{}rem", p.position.unwrap().0*5),
+                        St::Top => format!("{}rem", top*5),
+                        St::Left => format!("{}rem", left*5),
                     ]
                 ]
             } else {
                 empty!()
             }
-        })
+        }),
+        if model.promotion.is_some() {
+            div![
+                C!["promotion"],
+                PROMOTION_PIECES.iter().map(|p| {
+                    let piece_type = *p;
+                    div![
+                        C!["promotion-choice"],
+                        img![attrs! {
+                            At::Src => format!("./imgs/{}{}.svg", player, piece_type)
+                        }],
+                        ev(Ev::Click, move |_| Msg::Promote(piece_type))
+                    ]
+                })
+            ]
+        } else {
+            empty!()
+        },
+        match model.status {
+            GameStatus::Ongoing => empty!(),
+            GameStatus::Checkmate(winner) => div![
+                C!["status"],
+                format!("Checkmate, {} wins", color_name(winner))
+            ],
+            GameStatus::Stalemate => div![C!["status"], "Stalemate"],
+            GameStatus::Draw(reason) => div![
+                C!["status"],
+                format!("Draw by {}", draw_reason_name(reason))
+            ],
+        },
+        div![
+            C!["controls"],
+            button![
+                "New game as White",
+                ev(Ev::Click, |_| Msg::NewGame(PieceColor::White))
+            ],
+            button![
+                "New game as Black",
+                ev(Ev::Click, |_| Msg::NewGame(PieceColor::Black))
+            ],
+            a![
+                C!["button"],
+                attrs! {
+                    At::Href => format!(
+                        "data:application/x-chess-pgn;charset=utf-8,{}",
+                        String::from(js_sys::encode_uri_component(&model.record.to_pgn()))
+                    ),
+                    At::Download => "game.pgn",
+                },
+                "Download PGN"
+            ],
+            [1000, 5000].iter().map(|think_time| {
+                let think_time = *think_time;
+                button![
+                    C![if model.think_time == think_time {
+                        "active"
+                    } else {
+                        ""
+                    }],
+                    format!("Think {}s", think_time / 1000),
+                    ev(Ev::Click, move |_| Msg::ThinkTime(think_time))
+                ]
+            }),
+            [
+                EvaluatorKind::Material,
+                EvaluatorKind::PieceSquares,
+                EvaluatorKind::Full,
+            ]
+            .iter()
+            .map(|evaluator| {
+                let evaluator = *evaluator;
+                button![
+                    C![if model.evaluator == evaluator {
+                        "active"
+                    } else {
+                        ""
+                    }],
+                    evaluator.name(),
+                    ev(Ev::Click, move |_| Msg::Evaluator(evaluator))
+                ]
+            }),
+        ],
+        div![
+            C!["moves"],
+            model.record.moves.iter().enumerate().map(|(i, mov)| {
+                let start = &model.record.start;
+                let ply = i + (start.side_to_move == PieceColor::Black) as usize;
+                let number = start.fullmove_number as usize + ply / 2;
+                // Numbers go before White's moves and a first move by Black
+                span![match (ply % 2, i) {
+                    (0, _) => format!("{}. {}", number, mov.san),
+                    (_, 0) => format!("{}... {}", number, mov.san),
+                    _ => mov.san.clone(),
+                }]
+            })
+        ],
+        div![
+            C!["fen"],
+            input![
+                attrs! {
+                    At::Value => model.board.to_fen(),
+                    At::SpellCheck => false,
+                },
+                input_ev(Ev::Change, Msg::LoadFen)
+            ],
+            textarea![
+                attrs! {
+                    At::Placeholder => "Paste a PGN game to continue it",
+                    At::SpellCheck => false,
+                    At::Rows => 4,
+                },
+                input_ev(Ev::Change, Msg::LoadPgn)
+            ],
+            model.error.as_ref().map(|error| div![C!["error"], error]),
+        ]
     ]
 }
 
+/// Square drawn at a screen column and row, the player's pieces are always
+/// drawn at the bottom
+fn square_at(player: PieceColor, column: usize, row: usize) -> Square {
+    match player {
+        PieceColor::White => Square::new(column, 7 - row),
+        PieceColor::Black => Square::new(7 - column, row),
+    }
+}
+/// Screen column and row where a square is drawn
+fn screen_position(player: PieceColor, square: Square) -> (usize, usize) {
+    match player {
+        PieceColor::White => (square.file(), 7 - square.rank()),
+        PieceColor::Black => (7 - square.file(), square.rank()),
+    }
+}
+
+fn draw_reason_name(reason: DrawReason) -> &'static str {
+    match reason {
+        DrawReason::FiftyMoveRule => "the fifty-move rule",
+        DrawReason::ThreefoldRepetition => "threefold repetition",
+        DrawReason::FivefoldRepetition => "fivefold repetition",
+        DrawReason::InsufficientMaterial => "insufficient material",
+    }
+}
+
+fn color_name(color: PieceColor) -> &'static str {
+    match color {
+        PieceColor::White => "White",
+        PieceColor::Black => "Black",
+    }
+}
+
 // ------ ------
 //     Start
 // ------ ------
@@ -1,51 +1,68 @@
-
 use crate::board::*;
+use crate::eval::*;
+use crate::transposition::*;
+#[cfg(target_arch = "wasm32")]
+use seed::prelude::js_sys;
 
-const MAX_DEPTH: u32 = 4;
+/// Deepest iteration, only reached when nothing else stops the search
+const MAX_DEPTH: u32 = 64;
+/// Score of a checkmate, large enough to outweigh any material difference
+const MATE: i32 = 100_000;
+/// Bound above any score, the starting search window
+const INFINITY: i32 = 1_000_000;
+/// Nodes visited between two looks at the clock, which calls into
+/// JavaScript in the browser
+const CLOCK_INTERVAL: u64 = 1024;
 
-pub struct Move {
-    pub start: (usize, usize),
-    pub end: (usize, usize),
-    value: i32,
+/// A move with the score the search gave it
+pub struct ScoredMove {
+    /// None when the player to move has no legal moves
+    pub mov: Option<Move>,
+    pub score: i32,
 }
-impl PartialEq for Move {
-    fn eq(&self, mov: &Move) -> bool {
-        self.value == mov.value
+impl ScoredMove {
+    fn new(mov: Move, score: i32) -> Self {
+        ScoredMove {
+            mov: Some(mov),
+            score,
+        }
     }
-}
-impl PartialOrd for Move {
-    fn partial_cmp(&self, other: &Move) -> Option<std::cmp::Ordering> {
-        self.value.partial_cmp(&other.value)
+    fn empty(score: i32) -> Self {
+        ScoredMove { mov: None, score }
     }
 }
-impl Eq for Move {}
-impl Ord for Move {
-    fn cmp(&self, other: &Move) -> std::cmp::Ordering {
-        self.value.cmp(&other.value)
-    }
+
+/// When to stop searching, the first limit reached wins
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Milliseconds to think
+    pub time: Option<u32>,
+    pub nodes: Option<u64>,
+    /// Plies searched, the first one counts
+    pub depth: Option<u32>,
 }
-impl Move {
-    pub fn new(x0: usize, y0: usize, x1: usize, y1: usize, points: i32) -> Self {
-        Move {
-            start: (x0, y0),
-            end: (x1, y1),
-            value: points,
-        }
-    }
-    pub fn max() -> Self {
-        Move {
-            start: (0, 0),
-            end: (0, 0),
-            value: i32::MAX,
-        }
-    }
-    pub fn min() -> Self {
-        Move {
-            start: (0, 0),
-            end: (0, 0),
-            value: i32::MIN,
-        }
-    }
+
+/// Iterative deepening alpha-beta search of the moves of the side to move
+pub struct Search<E: Evaluator> {
+    pub limits: SearchLimits,
+    /// Scores the quiet positions, changed with `set_evaluator`
+    evaluator: E,
+    /// Plies searched in the current iteration, only captures are followed
+    /// after that
+    max_depth: u32,
+    /// Depth of the last completed iteration
+    pub depth: u32,
+    /// Positions visited so far
+    pub nodes: u64,
+    /// Best move of the previous iteration, searched first
+    hint: Option<Move>,
+    /// Results kept from one iteration to the next, and from one move to the
+    /// next in the same game
+    table: TranspositionTable,
+    /// Time the search started, in milliseconds
+    start: f64,
+    /// Set when a limit was reached in the middle of an iteration
+    stopped: bool,
 }
 
 pub fn points(piece: PieceType) -> i32 {
@@ -59,66 +76,431 @@ pub fn points(piece: PieceType) -> i32 {
     }
 }
 
-pub fn maximize(board: ChessBoard, depth: u32) -> Move {
-    let mut best_move = Move::min();
-    /* super::log(&format!("{:?}",board.pieces));
-    super::log(&board.to_string()); */
-
-    for piece in &board.pieces {
-        if let ChessPiece {
-            position: Some((x, y)),
-            piece_color: PieceColor::Black,
-            ..
-        } = piece
-        {
-            for (movx, movy) in board.get_possible_moves(*x, *y) {
-                let value: i32 = if let Some(p) = board.get(movx, movy) {
-                    points(p.piece_type)
-                } else {
-                    0
+/// Material won by a move, to try the biggest captures first
+fn gain(mov: &Move) -> i32 {
+    let mut value = mov.captured.map_or(0, points);
+    if let Some(p) = mov.promotion {
+        value += points(p) - points(PieceType::Pawn);
+    }
+    value
+}
+
+impl SearchLimits {
+    pub fn time(milliseconds: u32) -> Self {
+        SearchLimits {
+            time: Some(milliseconds),
+            ..SearchLimits::default()
+        }
+    }
+}
+
+impl<E: Evaluator> Search<E> {
+    /// Search with a transposition table of `table_size` megabytes
+    pub fn new(limits: SearchLimits, table_size: usize, evaluator: E) -> Self {
+        Search {
+            limits,
+            evaluator,
+            max_depth: 0,
+            depth: 0,
+            nodes: 0,
+            hint: None,
+            table: TranspositionTable::new(table_size),
+            start: 0.0,
+            stopped: false,
+        }
+    }
+    /// Forgets the results of earlier searches, for a position from another
+    /// game
+    pub fn clear_table(&mut self) {
+        self.table.clear();
+    }
+    /// Scores positions with another evaluator. The table is cleared, the
+    /// scores it holds came from the previous one.
+    pub fn set_evaluator(&mut self, evaluator: E) {
+        self.evaluator = evaluator;
+        self.clear_table();
+    }
+    /// Finds the best move for the player to move, scoring lines from their
+    /// point of view. Searches one ply deeper at a time and returns the
+    /// result of the last iteration that finished within the limits.
+    pub fn best_move(&mut self, board: &mut ChessBoard) -> ScoredMove {
+        self.start = now();
+        self.nodes = 0;
+        self.hint = None;
+        self.stopped = false;
+        let mut best_move = ScoredMove::empty(0);
+        for max_depth in 1..=self.limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH) {
+            self.max_depth = max_depth;
+            let result = self.negamax(board, 0, -INFINITY, INFINITY);
+            if self.stopped {
+                break;
+            }
+            self.depth = max_depth;
+            self.hint = result.mov;
+            best_move = result;
+            // Searching deeper can't find a faster mate
+            if best_move.mov.is_none() || best_move.score.abs() > MATE - MAX_DEPTH as i32 {
+                break;
+            }
+        }
+        best_move
+    }
+    /// Whether a limit was reached. The first iteration always completes so
+    /// that there is a move to play. The time is only checked every
+    /// `CLOCK_INTERVAL` nodes.
+    fn out_of_budget(&self) -> bool {
+        if self.max_depth == 1 {
+            return false;
+        }
+        matches!(self.limits.nodes, Some(nodes) if self.nodes >= nodes)
+            || (self.nodes.is_multiple_of(CLOCK_INTERVAL)
+                && matches!(self.limits.time, Some(time) if now() - self.start >= f64::from(time)))
+    }
+    /// Best move and its score for the side to move, which is exact inside
+    /// the `alpha`..`beta` window and only a bound outside of it
+    fn negamax(
+        &mut self,
+        board: &mut ChessBoard,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> ScoredMove {
+        self.nodes += 1;
+        if self.out_of_budget() {
+            self.stopped = true;
+        }
+        if self.stopped {
+            // The result of the iteration is thrown away
+            return ScoredMove::empty(0);
+        }
+        if depth > 0 && board.draw_reason().is_some() {
+            return ScoredMove::empty(0);
+        }
+        // Past the horizon only captures and promotions are searched, until
+        // the position is quiet
+        let horizon = depth >= self.max_depth;
+        let remaining = self.max_depth.saturating_sub(depth);
+        let mut hint = None;
+        if let Some(entry) = self.table.get(board.hash).filter(|_| !horizon) {
+            hint = entry.mov;
+            let score = from_table(entry.score, depth);
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            // The root always searches its moves to break ties the same way
+            if depth > 0 && entry.depth >= remaining && cutoff {
+                return ScoredMove {
+                    mov: entry.mov,
+                    score,
                 };
-                if depth == MAX_DEPTH {
-                    best_move = std::cmp::max(best_move, Move::new(*x, *y, movx, movy, value))
-                } else {
-                    let mut cloned = board.clone();
-                    cloned.make_move((*x, *y), (movx, movy));
-                    let seq = minimize(cloned, depth + 1);
-                    best_move =
-                        std::cmp::max(best_move, Move::new(*x, *y, movx, movy, seq.value + value))
-                }
             }
         }
+        let moves = board.legal_moves();
+        let in_check = board.is_in_check(board.side_to_move);
+        if moves.is_empty() {
+            // Prefer being mated later, a stalemate is a draw
+            return ScoredMove::empty(if in_check { -MATE + depth as i32 } else { 0 });
+        }
+        let alpha_start = alpha;
+        let mut best_move = ScoredMove::empty(-INFINITY);
+        let mut best_index = 0;
+        let quiescence = horizon && !in_check;
+        if quiescence {
+            // Stand pat: the side to move doesn't have to capture anything,
+            // but it has to answer a check
+            best_move.score = self.evaluator.evaluate(board);
+            alpha = alpha.max(best_move.score);
+            if alpha >= beta {
+                return best_move;
+            }
+        }
+        // Trying the previous best move and then the biggest captures first
+        // makes cutoffs come sooner, the index in generation order still
+        // decides ties
+        if depth == 0 {
+            hint = self.hint;
+        }
+        let mut moves: Vec<(usize, Move)> = moves
+            .into_iter()
+            .enumerate()
+            .filter(|(_, mov)| !quiescence || gain(mov) > 0)
+            .collect();
+        moves.sort_by_key(|(_, mov)| (Some(*mov) != hint, -gain(mov)));
+        for (index, mov) in moves {
+            // At the root a move tying with the best one still gets an exact
+            // score, so that later moves win ties
+            let floor = if depth == 0 { alpha - 1 } else { alpha };
+            let undo = board.make_move(mov);
+            let value = -self.negamax(board, depth + 1, -beta, -floor).score;
+            board.unmake_move(mov, undo);
+            if self.stopped {
+                return ScoredMove::empty(0);
+            }
+            if value > best_move.score || (value == best_move.score && index > best_index) {
+                best_move = ScoredMove::new(mov, value);
+                best_index = index;
+            }
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                // The opponent won't allow this line
+                break;
+            }
+        }
+        if horizon {
+            return best_move;
+        }
+        let bound = if best_move.score <= alpha_start {
+            Bound::Upper
+        } else if best_move.score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(Entry {
+            key: board.hash,
+            depth: remaining,
+            bound,
+            score: to_table(best_move.score, depth),
+            // Every move failed low, none of them is known to be better
+            mov: if bound == Bound::Upper {
+                None
+            } else {
+                best_move.mov
+            },
+        });
+        best_move
     }
-    best_move
 }
-pub fn minimize(board: ChessBoard, depth: u32) -> Move {
-    let mut best_move = Move::max();
-    /* super::log(&format!("{:?}",board.pieces));
-    super::log(&board.to_string()); */
-    for piece in &board.pieces {
-        if let ChessPiece {
-            position: Some((x, y)),
-            piece_color: PieceColor::White,
-            ..
-        } = piece
-        {
-            for (movx, movy) in board.get_possible_moves(*x, *y) {
-                let value: i32 = if let Some(p) = board.get(movx, movy) {
-                    -points(p.piece_type)
-                } else {
-                    0
-                };
-                if depth == MAX_DEPTH {
-                    best_move = std::cmp::min(best_move, Move::new(*x, *y, movx, movy, value))
-                } else {
-                    let mut cloned = board.clone();
-                    cloned.make_move((*x, *y), (movx, movy));
-                    let seq = maximize(cloned, depth + 1);
-                    best_move =
-                        std::cmp::min(best_move, Move::new(*x, *y, movx, movy, seq.value + value))
+
+/// Mate scores count plies from the root, the table counts them from the
+/// stored position so they stay right when it's reached at another depth
+fn to_table(score: i32, depth: u32) -> i32 {
+    match score {
+        s if s > MATE / 2 => s + depth as i32,
+        s if s < -MATE / 2 => s - depth as i32,
+        s => s,
+    }
+}
+fn from_table(score: i32, depth: u32) -> i32 {
+    match score {
+        s if s > MATE / 2 => s - depth as i32,
+        s if s < -MATE / 2 => s + depth as i32,
+        s => s,
+    }
+}
+
+/// Milliseconds elapsed since an arbitrary moment
+#[cfg(target_arch = "wasm32")]
+fn now() -> f64 {
+    js_sys::Date::now()
+}
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plain minimax visiting the whole tree, the reference for alpha-beta.
+    /// Its quiescence search has no cutoffs either, so it is only usable on
+    /// positions with few captures.
+    fn minimax(board: &mut ChessBoard, depth: u32, plies: u32, nodes: &mut u64) -> ScoredMove {
+        *nodes += 1;
+        if depth > 0 && board.draw_reason().is_some() {
+            return ScoredMove::empty(0);
+        }
+        let moves = board.legal_moves();
+        let in_check = board.is_in_check(board.side_to_move);
+        if moves.is_empty() {
+            return ScoredMove::empty(if in_check { -MATE + depth as i32 } else { 0 });
+        }
+        let quiescence = depth >= plies && !in_check;
+        let mut best_move = ScoredMove::empty(if quiescence {
+            Weights::default().evaluate(board)
+        } else {
+            -INFINITY
+        });
+        for mov in moves {
+            if quiescence && gain(&mov) == 0 {
+                continue;
+            }
+            let undo = board.make_move(mov);
+            let value = -minimax(board, depth + 1, plies, nodes).score;
+            board.unmake_move(mov, undo);
+            if value >= best_move.score {
+                best_move = ScoredMove::new(mov, value);
+            }
+        }
+        best_move
+    }
+
+    fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+    #[test]
+    fn alpha_beta_matches_minimax() {
+        // The reference also searches every capture sequence, which blows up
+        // with many pieces on the board
+        let positions = [
+            "4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1",
+            "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+            "r5k1/5ppp/8/3p4/4P3/2N5/5PPP/3R2K1 w - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        let (mut minimax_nodes, mut alpha_beta_nodes) = (0, 0);
+        for fen in &positions {
+            for plies in 1..=3 {
+                let mut board = ChessBoard::from_fen(fen).unwrap();
+                let expected = minimax(&mut board, 0, plies, &mut minimax_nodes);
+                let mut search = Search::new(depth(plies), 0, Weights::default());
+                let found = search.best_move(&mut board);
+                alpha_beta_nodes += search.nodes;
+                assert_eq!(found.mov, expected.mov, "{} at depth {}", fen, plies);
+                assert_eq!(found.score, expected.score, "{} at depth {}", fen, plies);
+            }
+        }
+        // Quiet positions where every move ties are barely pruned, the
+        // others a lot
+        assert!(
+            alpha_beta_nodes * 3 < minimax_nodes,
+            "alpha-beta: {} nodes, minimax: {} nodes",
+            alpha_beta_nodes,
+            minimax_nodes
+        );
+    }
+    #[test]
+    fn finds_mate_in_one() {
+        let mut board = ChessBoard::from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        )
+        .unwrap();
+        let found = Search::new(depth(3), 1, Weights::default()).best_move(&mut board);
+        let mov = found.mov.unwrap();
+        assert_eq!(
+            (mov.from.to_string(), mov.to.to_string()),
+            ("h5".into(), "f7".into())
+        );
+        assert_eq!(found.score, MATE - 1);
+    }
+    #[test]
+    fn captures_are_resolved_past_the_horizon() {
+        // The pawn on d5 is defended, taking it loses the knight
+        let mut board = ChessBoard::from_fen("k7/8/2p5/3p4/5N2/8/7P/4K3 w - - 0 1").unwrap();
+        let found = Search::new(depth(1), 0, Weights::default()).best_move(&mut board);
+        assert_ne!(found.mov.map(|mov| mov.to.to_string()), Some("d5".into()));
+
+        let mut board = ChessBoard::from_fen("k7/8/8/3p4/5N2/8/7P/4K3 w - - 0 1").unwrap();
+        let found = Search::new(depth(1), 0, Weights::default()).best_move(&mut board);
+        let mov = found.mov.unwrap();
+        assert_eq!(mov.to.to_string(), "d5");
+        // Black has nothing to take back, the line ends right after
+        board.make_move(mov);
+        assert_eq!(found.score, -Weights::default().evaluate(&board));
+    }
+    #[test]
+    fn table_is_kept_between_searches() {
+        let mut board = ChessBoard::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+        let mut search = Search::new(depth(4), 1, Weights::default());
+        let first = search.best_move(&mut board);
+        let nodes = search.nodes;
+        let again = search.best_move(&mut board);
+        assert_eq!(again.score, first.score);
+        assert!(
+            search.nodes < nodes,
+            "{} nodes, {} the first time",
+            search.nodes,
+            nodes
+        );
+        search.clear_table();
+        search.best_move(&mut board);
+        assert_eq!(search.nodes, nodes);
+        search.best_move(&mut board);
+        search.set_evaluator(Weights::default());
+        search.best_move(&mut board);
+        assert_eq!(search.nodes, nodes);
+    }
+    #[test]
+    fn search_leaves_the_board_as_it_was() {
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = ChessBoard::from_fen(kiwipete).unwrap();
+        let before = board.clone();
+        let mut search = Search::new(depth(2), 1, Weights::default());
+        assert!(search.best_move(&mut board).mov.is_some());
+        assert_eq!(board, before);
+    }
+    #[test]
+    fn searches_with_any_evaluator() {
+        /// Wants the white king as far as possible from the a file
+        struct KingFile;
+        impl Evaluator for KingFile {
+            fn evaluate(&self, board: &ChessBoard) -> i32 {
+                let file = board.king_position(PieceColor::White).unwrap().file() as i32;
+                match board.side_to_move {
+                    PieceColor::White => file,
+                    PieceColor::Black => -file,
                 }
             }
         }
+        let mut board = ChessBoard::from_fen("4k3/7p/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+        let found = Search::new(depth(1), 0, KingFile).best_move(&mut board);
+        assert_eq!(found.mov.unwrap().to.file(), 5);
+        assert_eq!(found.score, 5);
+    }
+    #[test]
+    fn stops_at_the_limits() {
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = ChessBoard::from_fen(kiwipete).unwrap();
+        let before = board.clone();
+        let limits = SearchLimits {
+            // The first iteration always completes, with its quiescence
+            // search it takes about 4,500 nodes here
+            nodes: Some(10_000),
+            ..SearchLimits::default()
+        };
+        let mut search = Search::new(limits, 0, Weights::default());
+        let found = search.best_move(&mut board);
+        assert_eq!(search.nodes, 10_000);
+        // The interrupted iteration is thrown away
+        let mut complete = Search::new(depth(search.depth), 0, Weights::default());
+        assert_eq!(found.mov, complete.best_move(&mut board).mov);
+        assert_eq!(board, before);
+
+        let mut board = ChessBoard::new();
+        let before = board.clone();
+        let start = now();
+        let mut search = Search::new(SearchLimits::time(100), 1, Weights::default());
+        assert!(search.best_move(&mut board).mov.is_some());
+        assert!(now() - start < 1000.0);
+        assert_eq!(board, before);
+    }
+    #[test]
+    fn transposition_table_saves_nodes() {
+        // Kings and rooks shuffling around reach the same positions often
+        let mut board = ChessBoard::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+        let mut without_table = Search::new(depth(5), 0, Weights::default());
+        let mut with_table = Search::new(depth(5), 1, Weights::default());
+        let expected = without_table.best_move(&mut board);
+        let found = with_table.best_move(&mut board);
+        assert_eq!(found.score, expected.score);
+        assert!(
+            with_table.nodes < without_table.nodes,
+            "{} nodes with a table, {} without",
+            with_table.nodes,
+            without_table.nodes
+        );
     }
-    best_move
 }
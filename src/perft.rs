@@ -0,0 +1,125 @@
+//==============================================================//
+//  PERFT
+//==============================================================//
+use crate::board::*;
+
+// Only used to check the move generator, the app never calls it
+#[allow(dead_code)]
+impl ChessBoard {
+    /// Number of move sequences of `depth` plies from the position, to compare
+    /// the move generator against known counts
+    pub fn perft(&self, depth: u32) -> u64 {
+        self.clone().count_leaves(depth)
+    }
+    /// Perft of the position after each legal move, to find which move a
+    /// wrong count comes from
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        let mut board = self.clone();
+        board
+            .legal_moves()
+            .into_iter()
+            .map(|mov| {
+                let undo = board.make_move(mov);
+                let count = board.count_leaves(depth.saturating_sub(1));
+                board.unmake_move(mov, undo);
+                (mov, count)
+            })
+            .collect()
+    }
+    fn count_leaves(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        // The last ply doesn't need to be played to be counted
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut count = 0;
+        for mov in moves {
+            let undo = self.make_move(mov);
+            count += self.count_leaves(depth - 1);
+            self.unmake_move(mov, undo);
+        }
+        count
+    }
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference positions with their node counts from depth 1
+    const POSITIONS: [(&str, &[u64]); 6] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197_281],
+        ),
+        // Kiwipete
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97_862, 4_085_603],
+        ),
+        (
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43_238, 674_624],
+        ),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422_333],
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62_379, 2_103_487],
+        ),
+        (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89_890],
+        ),
+    ];
+
+    fn check(max_nodes: u64) {
+        for (fen, counts) in &POSITIONS {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            for (depth, expected) in counts.iter().enumerate() {
+                if *expected > max_nodes {
+                    break;
+                }
+                let depth = depth as u32 + 1;
+                assert_eq!(board.perft(depth), *expected, "{} at depth {}", fen, depth);
+            }
+        }
+    }
+    #[test]
+    fn reference_positions() {
+        check(10_000);
+    }
+    /// Slow without optimizations, run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn reference_positions_deep() {
+        check(u64::MAX);
+    }
+    #[test]
+    fn divide_splits_perft() {
+        let board = ChessBoard::new();
+        let divided = board.divide(3);
+        assert_eq!(divided.len(), 20);
+        assert_eq!(divided.iter().map(|(_, n)| n).sum::<u64>(), 8902);
+        let count = |name: &str| {
+            divided
+                .iter()
+                .find(|(mov, _)| format!("{}{}", mov.from, mov.to) == name)
+                .map(|(_, n)| *n)
+        };
+        assert_eq!(count("e2e4"), Some(600));
+        assert_eq!(count("d2d4"), Some(560));
+        assert_eq!(count("b1c3"), Some(440));
+        assert_eq!(count("e2e5"), None);
+        assert_eq!(board.perft(0), 1);
+    }
+}
@@ -0,0 +1,636 @@
+//==============================================================//
+//  GAME RECORD, PGN EXPORT AND IMPORT
+//==============================================================//
+use crate::board::*;
+use crate::fen::FenError;
+use crate::san::*;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// PGN lines shouldn't be longer than this
+const LINE_LENGTH: usize = 80;
+
+/// Moves played in a game, with the information needed to write it as PGN
+#[derive(Clone)]
+pub struct GameRecord {
+    /// Tag pairs, starting with the Seven Tag Roster
+    pub tags: Vec<(String, String)>,
+    /// Position the game started from
+    pub start: ChessBoard,
+    pub moves: Vec<RecordedMove>,
+    /// Status after the last move
+    pub status: GameStatus,
+}
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub mov: Move,
+    /// Standard Algebraic Notation, like "Nbd7" or "exd8=Q+"
+    pub san: String,
+}
+
+impl GameRecord {
+    pub fn new(start: &ChessBoard) -> Self {
+        let mut record = GameRecord {
+            tags: vec![],
+            start: start.clone(),
+            moves: vec![],
+            status: start.status(),
+        };
+        for (name, value) in &[
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ] {
+            record.set_tag(name, value);
+        }
+        let fen = start.to_fen();
+        if fen != START_FEN {
+            record.set_tag("SetUp", "1");
+            record.set_tag("FEN", &fen);
+        }
+        record.update_result();
+        record
+    }
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+    /// Records a move, `board` is the position before it's played
+    pub fn push(&mut self, board: &ChessBoard, mov: Move) {
+        let mut after = board.clone();
+        after.make_move(mov);
+        self.status = after.status();
+        self.moves.push(RecordedMove {
+            mov,
+            san: to_san(board, &mov),
+        });
+        self.update_result();
+    }
+    /// Game termination marker: "1-0", "0-1", "1/2-1/2" or "*" if unfinished
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+    /// Sets the result when the game ended on the board, other results (like
+    /// a resignation) have to be set through the "Result" tag
+    fn update_result(&mut self) {
+        let result = match self.status {
+            GameStatus::Ongoing => return,
+            GameStatus::Checkmate(PieceColor::White) => "1-0",
+            GameStatus::Checkmate(PieceColor::Black) => "0-1",
+            GameStatus::Stalemate | GameStatus::Draw(_) => "1/2-1/2",
+        };
+        self.set_tag("Result", result);
+    }
+    /// Replays the first `ply` moves of the game from the starting position
+    pub fn position_after(&self, ply: usize) -> ChessBoard {
+        let mut board = self.start.clone();
+        for recorded in self.moves.iter().take(ply) {
+            board.make_move(recorded.mov);
+        }
+        board
+    }
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn += &format!("[{} \"{}\"]\n", name, value);
+        }
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        let mut number = self.start.fullmove_number;
+        let mut color = self.start.side_to_move;
+        if color == PieceColor::Black && !self.moves.is_empty() {
+            tokens.push(format!("{}...", number));
+        }
+        for mov in &self.moves {
+            if color == PieceColor::White {
+                tokens.push(format!("{}.", number));
+            } else {
+                number += 1;
+            }
+            tokens.push(mov.san.clone());
+            color = color.opponent();
+        }
+        tokens.push(self.result().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        pgn += &line;
+        pgn.push('\n');
+        pgn
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+    /// Malformed tag pair on the given line
+    InvalidTag(usize),
+    /// Comment opened on the given line and never closed
+    UnterminatedComment(usize),
+    /// A variation was closed without being opened or never closed
+    UnbalancedVariation,
+    InvalidFen {
+        game: usize,
+        error: FenError,
+    },
+    /// The token isn't a move in Standard Algebraic Notation
+    InvalidMove {
+        game: usize,
+        ply: usize,
+        token: String,
+    },
+    IllegalMove {
+        game: usize,
+        ply: usize,
+        token: String,
+    },
+    /// More than one piece can make the move
+    AmbiguousMove {
+        game: usize,
+        ply: usize,
+        token: String,
+    },
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::InvalidTag(line) => write!(f, "invalid tag pair on line {}", line),
+            PgnError::UnterminatedComment(line) => {
+                write!(f, "comment on line {} is never closed", line)
+            }
+            PgnError::UnbalancedVariation => write!(f, "unbalanced parentheses"),
+            PgnError::InvalidFen { game, error } => {
+                write!(f, "game {}: invalid FEN tag: {}", game, error)
+            }
+            PgnError::InvalidMove { game, ply, token } => {
+                write!(f, "game {}, ply {}: '{}' is not a move", game, ply, token)
+            }
+            PgnError::IllegalMove { game, ply, token } => {
+                write!(f, "game {}, ply {}: '{}' is illegal", game, ply, token)
+            }
+            PgnError::AmbiguousMove { game, ply, token } => {
+                write!(f, "game {}, ply {}: '{}' is ambiguous", game, ply, token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Move(String),
+    Result(String),
+    VariationStart,
+    VariationEnd,
+}
+
+/// Reads every game in a PGN file. Comments, NAGs and variations are
+/// skipped, only the main line is replayed.
+pub fn read_pgn(pgn: &str) -> Result<Vec<GameRecord>, PgnError> {
+    let mut games = vec![];
+    let mut tags = vec![];
+    let mut moves = vec![];
+    let mut in_movetext = false;
+    let mut depth = 0;
+    for token in tokenize(pgn)? {
+        match token {
+            Token::Tag(name, value) => {
+                // Tags after some moves belong to the next game
+                if in_movetext {
+                    games.push(replay(games.len() + 1, &tags, &moves, None)?);
+                    tags.clear();
+                    moves.clear();
+                    in_movetext = false;
+                }
+                tags.push((name, value));
+            }
+            Token::VariationStart => {
+                in_movetext = true;
+                depth += 1;
+            }
+            Token::VariationEnd => {
+                if depth == 0 {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                depth -= 1;
+            }
+            Token::Move(san) => {
+                in_movetext = true;
+                if depth == 0 {
+                    moves.push(san);
+                }
+            }
+            Token::Result(result) => {
+                if depth == 0 {
+                    games.push(replay(games.len() + 1, &tags, &moves, Some(&result))?);
+                    tags.clear();
+                    moves.clear();
+                    in_movetext = false;
+                }
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    if in_movetext || !tags.is_empty() {
+        games.push(replay(games.len() + 1, &tags, &moves, None)?);
+    }
+    Ok(games)
+}
+
+fn tokenize(pgn: &str) -> Result<Vec<Token>, PgnError> {
+    let chars: Vec<char> = pgn.chars().collect();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\n' => {
+                line += 1;
+                i += 1;
+            }
+            c if c.is_whitespace() => i += 1,
+            // Rest of line comments and escaped lines
+            ';' | '%' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '{' => {
+                let start = line;
+                while i < chars.len() && chars[i] != '}' {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(PgnError::UnterminatedComment(start));
+                }
+                i += 1;
+            }
+            '[' => {
+                let (tag, end) = read_tag(&chars, i).ok_or(PgnError::InvalidTag(line))?;
+                tokens.push(tag);
+                i = end;
+            }
+            '(' => {
+                tokens.push(Token::VariationStart);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::VariationEnd);
+                i += 1;
+            }
+            // Numeric Annotation Glyph
+            '$' => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !"{}()[];$".contains(chars[i])
+                {
+                    i += 1;
+                }
+                let symbol: String = chars[start..i].iter().collect();
+                if let Some(token) = read_symbol(&symbol) {
+                    tokens.push(token);
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a `[Name "value"]` tag pair starting at `start`, returns the tag and
+/// the index after it
+fn read_tag(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let skip_spaces = |i: &mut usize| {
+        while *i < chars.len() && chars[*i] != '\n' && chars[*i].is_whitespace() {
+            *i += 1;
+        }
+    };
+    skip_spaces(&mut i);
+    let mut name = String::new();
+    while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+        name.push(chars[i]);
+        i += 1;
+    }
+    skip_spaces(&mut i);
+    if name.is_empty() || chars.get(i) != Some(&'"') {
+        return None;
+    }
+    i += 1;
+    let mut value = String::new();
+    loop {
+        match chars.get(i)? {
+            '"' => break,
+            '\\' => {
+                i += 1;
+                value.push(*chars.get(i)?);
+            }
+            '\n' => return None,
+            c => value.push(*c),
+        }
+        i += 1;
+    }
+    i += 1;
+    skip_spaces(&mut i);
+    if chars.get(i) != Some(&']') {
+        return None;
+    }
+    Some((Token::Tag(name, value), i + 1))
+}
+
+/// Classifies a movetext symbol, dropping move numbers and annotations
+fn read_symbol(symbol: &str) -> Option<Token> {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&symbol) {
+        return Some(Token::Result(symbol.to_string()));
+    }
+    let mut san = symbol;
+    // Move numbers like "12." or "12...", possibly glued to the move
+    if !san.starts_with("0-0") {
+        san = san.trim_start_matches(|c: char| c.is_ascii_digit());
+        if san.len() == symbol.len() || san.starts_with('.') || san.is_empty() {
+            san = san.trim_start_matches('.');
+        } else {
+            san = symbol;
+        }
+    }
+    let san = san.trim_end_matches(['!', '?']);
+    if san.is_empty() {
+        None
+    } else {
+        Some(Token::Move(san.to_string()))
+    }
+}
+
+/// Plays the moves of a game on the position described by its tags
+fn replay(
+    game: usize,
+    tags: &[(String, String)],
+    moves: &[String],
+    result: Option<&str>,
+) -> Result<GameRecord, PgnError> {
+    let fen = tags.iter().find(|(name, _)| name == "FEN");
+    let mut board = match fen {
+        Some((_, fen)) => {
+            ChessBoard::from_fen(fen).map_err(|error| PgnError::InvalidFen { game, error })?
+        }
+        None => ChessBoard::new(),
+    };
+    let mut record = GameRecord::new(&board);
+    for (name, value) in tags {
+        record.set_tag(name, value);
+    }
+    for (i, token) in moves.iter().enumerate() {
+        let ply = i + 1;
+        let mov = parse_san(&board, token).map_err(|error| match error {
+            SanError::Invalid => PgnError::InvalidMove {
+                game,
+                ply,
+                token: token.clone(),
+            },
+            SanError::Illegal => PgnError::IllegalMove {
+                game,
+                ply,
+                token: token.clone(),
+            },
+            SanError::Ambiguous => PgnError::AmbiguousMove {
+                game,
+                ply,
+                token: token.clone(),
+            },
+        })?;
+        record.push(&board, mov);
+        board.make_move(mov);
+    }
+    if let Some(result) = result {
+        record.set_tag("Result", result);
+    }
+    Ok(record)
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(record: &mut GameRecord, board: &mut ChessBoard, moves: &[(&str, &str)]) {
+        for (origin, destination) in moves {
+            let origin = origin.parse().unwrap();
+            let destination = destination.parse().unwrap();
+            assert!(board.can_move(origin, destination));
+            let mov = board.new_move(origin, destination, None).unwrap();
+            record.push(board, mov);
+            board.make_move(mov);
+        }
+    }
+    #[test]
+    fn scholars_mate() {
+        let mut board = ChessBoard::new();
+        let mut record = GameRecord::new(&board);
+        record.set_tag("White", "Player");
+        record.set_tag("Black", "ChessRS");
+        play(
+            &mut record,
+            &mut board,
+            &[
+                ("e2", "e4"),
+                ("e7", "e5"),
+                ("f1", "c4"),
+                ("b8", "c6"),
+                ("d1", "h5"),
+                ("g8", "f6"),
+                ("h5", "f7"),
+            ],
+        );
+        assert_eq!(
+            record.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"Player\"]\n[Black \"ChessRS\"]\n[Result \"1-0\"]\n\n\
+             1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n"
+        );
+    }
+    #[test]
+    fn castling_and_disambiguation() {
+        let mut board = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let mut record = GameRecord::new(&board);
+        play(
+            &mut record,
+            &mut board,
+            &[("e1", "g1"), ("e8", "c8"), ("a1", "a7"), ("h8", "h1")],
+        );
+        let moves: Vec<&str> = record.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(moves, vec!["O-O", "O-O-O", "Ra7", "Rh1+"]);
+
+        let mut board = ChessBoard::from_fen("4k3/8/8/N7/8/8/8/N3K2N w - - 0 1").unwrap();
+        let mut record = GameRecord::new(&board);
+        play(&mut record, &mut board, &[("a5", "b3")]);
+        let mut board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/N3K2N w - - 0 1").unwrap();
+        play(&mut record, &mut board, &[("h1", "g3")]);
+        let mut board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/N1N1K2N w - - 0 1").unwrap();
+        play(&mut record, &mut board, &[("a1", "b3")]);
+        let moves: Vec<&str> = record.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(moves, vec!["N5b3", "Ng3", "Nab3"]);
+    }
+    #[test]
+    fn promotion_from_position() {
+        let board = ChessBoard::from_fen("1r5k/P7/8/8/8/8/8/K7 b - - 0 40").unwrap();
+        let mut record = GameRecord::new(&board);
+        let mut after = board.clone();
+        let mov = board
+            .new_move("b8".parse().unwrap(), "b7".parse().unwrap(), None)
+            .unwrap();
+        record.push(&board, mov);
+        after.make_move(mov);
+        let mov = after
+            .new_move(
+                "a7".parse().unwrap(),
+                "a8".parse().unwrap(),
+                Some(PieceType::Knight),
+            )
+            .unwrap();
+        record.push(&after, mov);
+
+        assert!(record
+            .tags
+            .contains(&("SetUp".to_string(), "1".to_string())));
+        assert!(record.to_pgn().ends_with("\n\n40... Rb7 41. a8=N *\n"));
+    }
+    const OPERA_GAME: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+
+1. e4 {King's pawn} e5 2. Nf3 $1 d6 (2... Nc6 3. Bb5 (3. Bc4 Bc5) a6) 3. d4 Bg4?!
+4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 ; sacrifice
+10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6
+15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0
+
+[Event "Endgame"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "?"]
+[Black "?"]
+[Result "1/2-1/2"]
+[SetUp "1"]
+[FEN "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"]
+
+1.exd6 Kf7 2.d7 Ke7 3.d8=Q+ Kxd8 1/2-1/2
+"#;
+    #[test]
+    fn read_games() {
+        let games = read_pgn(OPERA_GAME).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let opera = &games[0];
+        assert_eq!(opera.tag("White"), Some("Paul Morphy"));
+        assert_eq!(opera.moves.len(), 33);
+        assert_eq!(opera.moves[22].san, "O-O-O");
+        assert_eq!(opera.status, GameStatus::Checkmate(PieceColor::White));
+        assert!(opera.to_pgn().contains(
+            "\n\n1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8.\n\
+             Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14.\n\
+             Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0\n"
+        ));
+
+        let endgame = &games[1];
+        let moves: Vec<&str> = endgame.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(moves, vec!["exd6", "Kf7", "d7", "Ke7", "d8=Q+", "Kxd8"]);
+        assert_eq!(endgame.result(), "1/2-1/2");
+        assert_eq!(
+            endgame.position_after(6).to_fen(),
+            "3k4/8/8/8/8/8/8/4K3 w - - 0 4"
+        );
+        assert!(endgame
+            .position_after(1)
+            .get("d5".parse().unwrap())
+            .is_none());
+    }
+    #[test]
+    fn round_trip() {
+        let games = read_pgn(OPERA_GAME).unwrap();
+        for game in &games {
+            let again = read_pgn(&game.to_pgn()).unwrap();
+            assert_eq!(again.len(), 1);
+            assert_eq!(again[0].to_pgn(), game.to_pgn());
+        }
+    }
+    #[test]
+    fn bad_moves_are_reported() {
+        assert_eq!(
+            read_pgn("1. e4 e5 2. Ke3 *").err(),
+            Some(PgnError::IllegalMove {
+                game: 1,
+                ply: 3,
+                token: "Ke3".to_string()
+            })
+        );
+        assert_eq!(
+            read_pgn("1. e4 e5 *\n\n1. d4 d5 2. Nf3 Nf6 3. Nd2 *").err(),
+            Some(PgnError::AmbiguousMove {
+                game: 2,
+                ply: 5,
+                token: "Nd2".to_string()
+            })
+        );
+        assert_eq!(
+            read_pgn("1. e4 e5 2. Nf3 Zz9").err(),
+            Some(PgnError::InvalidMove {
+                game: 1,
+                ply: 4,
+                token: "Zz9".to_string()
+            })
+        );
+        assert_eq!(
+            read_pgn("[Event \"?]\n1. e4").err(),
+            Some(PgnError::InvalidTag(1))
+        );
+        assert_eq!(
+            read_pgn("1. e4 {\ncomment\n").err(),
+            Some(PgnError::UnterminatedComment(1))
+        );
+        assert_eq!(
+            read_pgn("1. e4 (1. d4 *").err(),
+            Some(PgnError::UnbalancedVariation)
+        );
+    }
+}
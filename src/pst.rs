@@ -0,0 +1,266 @@
+//==============================================================//
+//  PIECE-SQUARE TABLES
+//==============================================================//
+use crate::bitboard::*;
+use crate::board::*;
+use crate::square::*;
+
+/// Phase with all the pieces on the board, 0 is a pure endgame
+pub const MAX_PHASE: i32 = 24;
+
+/// Every piece type, from the pawn to the king
+pub const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+/// Bonus of a piece on each square, in centipawns. The tables are drawn from
+/// White's side with a8 first so that they read like a board. Values from
+/// Ronald Friederich's PeSTO.
+type Table = [i32; 64];
+
+#[rustfmt::skip]
+const PAWN_MIDDLEGAME: Table = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const PAWN_ENDGAME: Table = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const KNIGHT_MIDDLEGAME: Table = [
+   -167, -89, -34, -49,  61, -97, -15,-107,
+    -73, -41,  72,  36,  23,  62,   7, -17,
+    -47,  60,  37,  65,  84, 129,  73,  44,
+     -9,  17,  19,  53,  37,  69,  18,  22,
+    -13,   4,  16,  13,  28,  19,  21,  -8,
+    -23,  -9,  12,  10,  19,  17,  25, -16,
+    -29, -53, -12,  -3,  -1,  18, -14, -19,
+   -105, -21, -58, -33, -17, -28, -19, -23,
+];
+#[rustfmt::skip]
+const KNIGHT_ENDGAME: Table = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+#[rustfmt::skip]
+const BISHOP_MIDDLEGAME: Table = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+#[rustfmt::skip]
+const BISHOP_ENDGAME: Table = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+#[rustfmt::skip]
+const ROOK_MIDDLEGAME: Table = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+#[rustfmt::skip]
+const ROOK_ENDGAME: Table = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+#[rustfmt::skip]
+const QUEEN_MIDDLEGAME: Table = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+#[rustfmt::skip]
+const QUEEN_ENDGAME: Table = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+#[rustfmt::skip]
+const KING_MIDDLEGAME: Table = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+#[rustfmt::skip]
+const KING_ENDGAME: Table = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+/// Material and placement of the pieces of `color` in centipawns: their
+/// middlegame and endgame values mixed by how many pieces are left
+pub fn piece_squares(board: &ChessBoard, color: PieceColor) -> i32 {
+    let (mut middlegame, mut endgame) = (0, 0);
+    for piece_type in &PIECE_TYPES {
+        for square in squares(board.pieces_of(color, *piece_type)) {
+            let (mg, eg) = piece_value(color, *piece_type, square);
+            middlegame += mg;
+            endgame += eg;
+        }
+    }
+    let phase = phase(board);
+    (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+/// How far the game is from the endgame, from `MAX_PHASE` with all the
+/// pieces on the board down to 0 with only kings and pawns
+pub fn phase(board: &ChessBoard) -> i32 {
+    let minors =
+        board.by_type[PieceType::Knight.index()] | board.by_type[PieceType::Bishop.index()];
+    let rooks = board.by_type[PieceType::Rook.index()];
+    let queens = board.by_type[PieceType::Queen.index()];
+    let phase = minors.count_ones() + 2 * rooks.count_ones() + 4 * queens.count_ones();
+    // Promotions can add pieces
+    (phase as i32).min(MAX_PHASE)
+}
+
+/// Middlegame and endgame value of a piece on a square, its material included
+fn piece_value(color: PieceColor, piece_type: PieceType, square: Square) -> (i32, i32) {
+    let (material, middlegame, endgame) = match piece_type {
+        PieceType::Pawn => ((82, 94), &PAWN_MIDDLEGAME, &PAWN_ENDGAME),
+        PieceType::Knight => ((337, 281), &KNIGHT_MIDDLEGAME, &KNIGHT_ENDGAME),
+        PieceType::Bishop => ((365, 297), &BISHOP_MIDDLEGAME, &BISHOP_ENDGAME),
+        PieceType::Rook => ((477, 512), &ROOK_MIDDLEGAME, &ROOK_ENDGAME),
+        PieceType::Queen => ((1025, 936), &QUEEN_MIDDLEGAME, &QUEEN_ENDGAME),
+        PieceType::King => ((0, 0), &KING_MIDDLEGAME, &KING_ENDGAME),
+    };
+    // Flipping the rank turns a1 = 0 into the index in the table, Black reads
+    // the tables upside down
+    let index = match color {
+        PieceColor::White => square.index() ^ 56,
+        PieceColor::Black => square.index(),
+    };
+    (material.0 + middlegame[index], material.1 + endgame[index])
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn sq(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+    #[test]
+    fn colors_read_the_tables_from_their_side() {
+        let board = ChessBoard::new();
+        assert_eq!(
+            piece_squares(&board, PieceColor::White),
+            piece_squares(&board, PieceColor::Black)
+        );
+        // The same position with the colors swapped and the board flipped
+        let white =
+            ChessBoard::from_fen("4k3/pp3ppp/2n5/3p4/8/2N2N2/PPP2PPP/4K2R w K - 0 1").unwrap();
+        let black =
+            ChessBoard::from_fen("4k2r/ppp2ppp/2n2n2/8/3P4/2N5/PP3PPP/4K3 b k - 0 1").unwrap();
+        assert_eq!(
+            piece_squares(&white, PieceColor::White),
+            piece_squares(&black, PieceColor::Black)
+        );
+        assert_eq!(
+            piece_squares(&white, PieceColor::Black),
+            piece_squares(&black, PieceColor::White)
+        );
+    }
+    #[test]
+    fn material_dominates() {
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let queen =
+            piece_squares(&board, PieceColor::White) - piece_squares(&board, PieceColor::Black);
+        assert!(queen > 900);
+    }
+    #[test]
+    fn phase_tapers_from_middlegame_to_endgame() {
+        assert_eq!(phase(&ChessBoard::new()), MAX_PHASE);
+        let endgame = ChessBoard::from_fen("4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(phase(&endgame), 0);
+        let rooks = ChessBoard::from_fen("r3k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(phase(&rooks), 4);
+        // The king belongs in the corner in the middlegame and in the center
+        // in the endgame
+        let king = |square| piece_value(PieceColor::White, PieceType::King, sq(square));
+        assert!(king("g1").0 > king("e4").0);
+        assert!(king("g1").1 < king("e4").1);
+    }
+    #[test]
+    fn pieces_prefer_the_center() {
+        let knight = |color, square| piece_value(color, PieceType::Knight, sq(square));
+        assert!(knight(PieceColor::White, "e4").0 > knight(PieceColor::White, "a1").0);
+        // Both colors read the same table from their own side
+        assert_eq!(
+            knight(PieceColor::White, "f3"),
+            knight(PieceColor::Black, "f6")
+        );
+    }
+}
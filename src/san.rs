@@ -0,0 +1,281 @@
+//==============================================================//
+//  STANDARD ALGEBRAIC NOTATION
+//==============================================================//
+use crate::board::*;
+use crate::square::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanError {
+    /// Not written in Standard Algebraic Notation
+    Invalid,
+    /// No legal move matches
+    Illegal,
+    /// More than one legal move matches
+    Ambiguous,
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Invalid => write!(f, "not a move"),
+            SanError::Illegal => write!(f, "illegal move"),
+            SanError::Ambiguous => write!(f, "ambiguous move"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+/// Standard Algebraic Notation of a move played on `board`, like "Nbd7",
+/// "exd6", "O-O-O", "e8=Q+" or "Qxf7#"
+pub fn to_san(board: &ChessBoard, mov: &Move) -> String {
+    let (origin, destination) = (mov.from, mov.to);
+    // The legal moves before and after the move are generated on this copy
+    let mut after = board.clone();
+    let mut san = String::new();
+    if mov.kind == MoveKind::Castle {
+        san += if destination.file() > origin.file() {
+            "O-O"
+        } else {
+            "O-O-O"
+        };
+    } else {
+        if mov.piece == PieceType::Pawn {
+            if mov.is_capture() {
+                san += &origin.to_string()[..1];
+            }
+        } else {
+            san += san_letter(mov.piece);
+            // Other pieces of the same kind that could move to the same square
+            let rivals: Vec<Square> = after
+                .legal_moves()
+                .iter()
+                .filter(|m| m.piece == mov.piece && m.to == destination && m.from != origin)
+                .map(|m| m.from)
+                .collect();
+            if !rivals.is_empty() {
+                let name = origin.to_string();
+                if rivals.iter().all(|rival| rival.file() != origin.file()) {
+                    san += &name[..1];
+                } else if rivals.iter().all(|rival| rival.rank() != origin.rank()) {
+                    san += &name[1..];
+                } else {
+                    san += &name;
+                }
+            }
+        }
+        if mov.is_capture() {
+            san.push('x');
+        }
+        san += &destination.to_string();
+        if let Some(promotion) = mov.promotion {
+            san.push('=');
+            san += san_letter(promotion);
+        }
+    }
+    after.make_move(*mov);
+    if after.is_in_check(after.side_to_move) {
+        san.push(if after.legal_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+/// Finds the legal move described in Standard Algebraic Notation, check and
+/// annotation suffixes are optional
+pub fn parse_san(board: &ChessBoard, san: &str) -> Result<Move, SanError> {
+    let moves = board.clone().legal_moves();
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let castling = match san {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    if let Some(file) = castling {
+        return moves
+            .into_iter()
+            .find(|m| m.kind == MoveKind::Castle && m.to.file() == file)
+            .ok_or(SanError::Illegal);
+    }
+
+    let mut rest = san;
+    // PROMOTION
+    let mut promotion = None;
+    if let Some(last) = rest.chars().last() {
+        if let Some(piece_type) = piece_from_letter(last) {
+            promotion = Some(piece_type);
+            rest = rest[..rest.len() - 1].trim_end_matches('=');
+        }
+    }
+    // PIECE
+    let mut piece_type = PieceType::Pawn;
+    if let Some(piece) = rest.chars().next().and_then(piece_from_letter) {
+        piece_type = piece;
+        rest = &rest[1..];
+    }
+    // DESTINATION
+    if rest.len() < 2 || !rest.is_char_boundary(rest.len() - 2) {
+        return Err(SanError::Invalid);
+    }
+    let destination: Square = rest[rest.len() - 2..]
+        .parse()
+        .map_err(|_| SanError::Invalid)?;
+    rest = &rest[..rest.len() - 2];
+    let capture = rest.ends_with('x');
+    rest = rest.trim_end_matches('x');
+    // DISAMBIGUATION
+    let mut file = None;
+    let mut rank = None;
+    for c in rest.chars() {
+        match c {
+            'a'..='h' if file.is_none() => file = Some(c as usize - 'a' as usize),
+            '1'..='8' if rank.is_none() => rank = Some(c as usize - '1' as usize),
+            _ => return Err(SanError::Invalid),
+        }
+    }
+    if piece_type == PieceType::Pawn {
+        // Pawns only change file when capturing
+        if capture != file.is_some() {
+            return Err(SanError::Invalid);
+        }
+        if file.is_none() {
+            file = Some(destination.file());
+        }
+    }
+
+    let candidates: Vec<Move> = moves
+        .into_iter()
+        .filter(|m| m.piece == piece_type && m.to == destination && m.kind != MoveKind::Castle)
+        .filter(|m| m.is_capture() == capture)
+        .filter(|m| {
+            file.unwrap_or(m.from.file()) == m.from.file()
+                && rank.unwrap_or(m.from.rank()) == m.from.rank()
+        })
+        .collect();
+    // The promotion piece must be given exactly when the pawn promotes
+    if candidates
+        .iter()
+        .any(|m| m.promotion.is_some() != promotion.is_some())
+        || promotion == Some(PieceType::King)
+    {
+        return Err(SanError::Invalid);
+    }
+    let candidates: Vec<&Move> = candidates
+        .iter()
+        .filter(|m| m.promotion == promotion)
+        .collect();
+    match candidates.as_slice() {
+        [] => Err(SanError::Illegal),
+        [mov] => Ok(**mov),
+        _ => Err(SanError::Ambiguous),
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+fn san_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+    }
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, origin: &str, destination: &str, promotion: Option<PieceType>) -> String {
+        let board = ChessBoard::from_fen(fen).unwrap();
+        let mov = board
+            .new_move(
+                origin.parse().unwrap(),
+                destination.parse().unwrap(),
+                promotion,
+            )
+            .unwrap();
+        to_san(&board, &mov)
+    }
+    #[test]
+    fn render() {
+        let opera = "rn2kb1r/p3qppp/2p2n2/1N2p1B1/2B1P3/1Q6/PPP2PPP/R3K2R b KQkq - 0 10";
+        assert_eq!(san(opera, "c6", "b5", None), "cxb5");
+        let opera = "rn2kb1r/p3qppp/5n2/1B2p1B1/4P3/1Q6/PPP2PPP/R3K2R b KQkq - 0 11";
+        assert_eq!(san(opera, "b8", "d7", None), "Nbd7");
+        let opera = "r3kb1r/p2nqppp/5n2/1B2p1B1/4P3/1Q6/PPP2PPP/R3K2R w KQkq - 1 12";
+        assert_eq!(san(opera, "e1", "c1", None), "O-O-O");
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(san(en_passant, "e5", "d6", None), "exd6");
+        let promotion = "8/3kP3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(promotion, "e7", "e8", Some(PieceType::Queen)), "e8=Q+");
+        assert_eq!(san(promotion, "e7", "e8", Some(PieceType::Knight)), "e8=N");
+        let scholar = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        assert_eq!(san(scholar, "h5", "f7", None), "Qxf7#");
+    }
+    #[test]
+    fn parse() {
+        let board =
+            ChessBoard::from_fen("r3kb1r/p2nqppp/5n2/1B2p1B1/4P3/1Q6/PPP2PPP/R3K2R w KQkq - 1 12")
+                .unwrap();
+        let mov = parse_san(&board, "O-O-O").unwrap();
+        assert_eq!(
+            (mov.from.to_string(), mov.to.to_string()),
+            ("e1".into(), "c1".into())
+        );
+        let mov = parse_san(&board, "Bxd7+!").unwrap();
+        assert_eq!(
+            (mov.from.to_string(), mov.to.to_string()),
+            ("b5".into(), "d7".into())
+        );
+        assert_eq!(parse_san(&board, "Qb8").err(), Some(SanError::Illegal));
+        assert_eq!(parse_san(&board, "exf5").err(), Some(SanError::Illegal));
+        assert_eq!(parse_san(&board, "ef5").err(), Some(SanError::Invalid));
+        assert_eq!(parse_san(&board, "Zz9").err(), Some(SanError::Invalid));
+        // The capture marker must match the move
+        assert_eq!(parse_san(&board, "Bd7").err(), Some(SanError::Illegal));
+        assert_eq!(
+            parse_san(&ChessBoard::new(), "Nxf3").err(),
+            Some(SanError::Illegal)
+        );
+
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/N1N1K2N w - - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "Nb3").err(), Some(SanError::Ambiguous));
+        let mov = parse_san(&board, "N1g3").unwrap();
+        assert_eq!(mov.from.to_string(), "h1");
+
+        let board = ChessBoard::from_fen("8/3kP3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "e8").err(), Some(SanError::Invalid));
+        let mov = parse_san(&board, "e8=R").unwrap();
+        assert_eq!(mov.promotion, Some(PieceType::Rook));
+    }
+    #[test]
+    fn every_legal_move_round_trips() {
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let promotions = "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1";
+        for fen in &[kiwipete, promotions] {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            for mov in board.clone().legal_moves() {
+                assert_eq!(parse_san(&board, &to_san(&board, &mov)), Ok(mov));
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//==============================================================//
+//  SQUARES
+//==============================================================//
+use std::fmt;
+use std::str::FromStr;
+
+/// One of the 64 squares of the board, numbered from a1 (0) to h8 (63)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseSquareError;
+
+impl Square {
+    /// Square on `file` (0 is the a-file) and `rank` (0 is the first rank).
+    /// Panics outside the board, use `offset` to move around safely.
+    pub fn new(file: usize, rank: usize) -> Square {
+        assert!(file < 8 && rank < 8, "square out of the board");
+        Square((rank * 8 + file) as u8)
+    }
+    /// Square with the given index, panics above 63
+    pub fn from_index(index: usize) -> Square {
+        assert!(index < 64, "square out of the board");
+        Square(index as u8)
+    }
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+    pub fn file(self) -> usize {
+        self.index() % 8
+    }
+    pub fn rank(self) -> usize {
+        self.index() / 8
+    }
+    /// Square `files` to the right and `ranks` up from White's point of view,
+    /// None when leaving the board
+    pub fn offset(self, files: i32, ranks: i32) -> Option<Square> {
+        let file = self.file() as i32 + files;
+        let rank = self.rank() as i32 + ranks;
+        if (0..8).contains(&file) && (0..8).contains(&rank) {
+            Some(Square::new(file as usize, rank as usize))
+        } else {
+            None
+        }
+    }
+    /// Every square, from a1 to h8
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            (b'a' + self.file() as u8) as char,
+            self.rank() + 1
+        )
+    }
+}
+
+impl FromStr for Square {
+    type Err = ParseSquareError;
+    /// Parses an algebraic square name like "e4"
+    fn from_str(name: &str) -> Result<Square, ParseSquareError> {
+        match name.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Ok(Square::new((file - b'a') as usize, (rank - b'1') as usize))
+            }
+            _ => Err(ParseSquareError),
+        }
+    }
+}
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid square name")
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn names() {
+        assert_eq!(Square::new(0, 0).to_string(), "a1");
+        assert_eq!(Square::new(4, 3).to_string(), "e4");
+        assert_eq!(Square::new(7, 7).to_string(), "h8");
+        for square in Square::all() {
+            assert_eq!(square.to_string().parse(), Ok(square));
+        }
+        assert_eq!("i1".parse::<Square>(), Err(ParseSquareError));
+        assert_eq!("a9".parse::<Square>(), Err(ParseSquareError));
+        assert_eq!("e".parse::<Square>(), Err(ParseSquareError));
+        assert_eq!("e44".parse::<Square>(), Err(ParseSquareError));
+    }
+    #[test]
+    fn coordinates() {
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!((e4.file(), e4.rank(), e4.index()), (4, 3, 28));
+        assert_eq!(Square::all().count(), 64);
+    }
+    #[test]
+    fn offsets_stay_on_the_board() {
+        let a1 = Square::new(0, 0);
+        let h8 = Square::new(7, 7);
+        assert_eq!(a1.offset(1, 2), "b3".parse().ok());
+        assert_eq!(h8.offset(-2, -1), "f7".parse().ok());
+        assert_eq!(a1.offset(-1, 0), None);
+        assert_eq!(a1.offset(0, -1), None);
+        assert_eq!(h8.offset(1, 0), None);
+        assert_eq!(h8.offset(0, 1), None);
+        assert_eq!(a1.offset(7, 7), Some(h8));
+    }
+}
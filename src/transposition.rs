@@ -0,0 +1,124 @@
+//==============================================================//
+//  TRANSPOSITION TABLE
+//==============================================================//
+use crate::board::*;
+
+/// How the stored score relates to the real score of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high, the score is at least this much
+    Lower,
+    /// The search failed low, the score is at most this much
+    Upper,
+}
+
+/// Result of searching a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Zobrist key of the position
+    pub key: u64,
+    /// Plies that were left to search
+    pub depth: u32,
+    pub bound: Bound,
+    pub score: i32,
+    pub mov: Option<Move>,
+}
+
+/// Fixed-size table of search results indexed by Zobrist key. A new entry
+/// replaces the old one unless that one is for the same key and deeper.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl TranspositionTable {
+    /// Table taking about `megabytes` of memory, no entries at all for 0
+    pub fn new(megabytes: usize) -> Self {
+        let count = megabytes * 1024 * 1024 / std::mem::size_of::<Option<Entry>>();
+        TranspositionTable {
+            entries: vec![None; count],
+        }
+    }
+    pub fn get(&self, key: u64) -> Option<&Entry> {
+        match self.slot(key) {
+            Some(index) => self.entries[index]
+                .as_ref()
+                .filter(|entry| entry.key == key),
+            None => None,
+        }
+    }
+    pub fn store(&mut self, entry: Entry) {
+        if let Some(index) = self.slot(entry.key) {
+            let slot = &mut self.entries[index];
+            match slot {
+                Some(old) if old.key == entry.key && old.depth > entry.depth => {}
+                _ => *slot = Some(entry),
+            }
+        }
+    }
+    /// Forgets every entry, keeping the memory
+    pub fn clear(&mut self) {
+        for entry in &mut self.entries {
+            *entry = None;
+        }
+    }
+    fn slot(&self, key: u64) -> Option<usize> {
+        match self.entries.len() {
+            0 => None,
+            len => Some((key % len as u64) as usize),
+        }
+    }
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: u64, depth: u32, score: i32) -> Entry {
+        Entry {
+            key,
+            depth,
+            bound: Bound::Exact,
+            score,
+            mov: None,
+        }
+    }
+    #[test]
+    fn sized_in_megabytes() {
+        let table = TranspositionTable::new(1);
+        let size = table.entries.len() * std::mem::size_of::<Option<Entry>>();
+        assert!(size <= 1024 * 1024);
+        assert!(size > 1000 * 1024);
+        assert!(TranspositionTable::new(0).entries.is_empty());
+    }
+    #[test]
+    fn store_and_replace() {
+        let mut table = TranspositionTable::new(1);
+        let len = table.entries.len() as u64;
+        table.store(entry(7, 3, 10));
+        assert_eq!(table.get(7).map(|e| e.score), Some(10));
+        // Another key for the same slot
+        assert_eq!(table.get(7 + len), None);
+        // Shallower results don't replace deeper ones for the same position
+        table.store(entry(7, 2, 20));
+        assert_eq!(table.get(7).map(|e| e.score), Some(10));
+        table.store(entry(7, 3, 30));
+        assert_eq!(table.get(7).map(|e| e.score), Some(30));
+        // But always replace other positions
+        table.store(entry(7 + len, 1, 40));
+        assert_eq!(table.get(7), None);
+        assert_eq!(table.get(7 + len).map(|e| e.score), Some(40));
+
+        let mut empty = TranspositionTable::new(0);
+        empty.store(entry(7, 3, 10));
+        assert_eq!(empty.get(7), None);
+
+        table.clear();
+        assert_eq!(table.get(7 + len), None);
+        assert_eq!(table.entries.len() as u64, len);
+    }
+}
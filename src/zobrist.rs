@@ -0,0 +1,90 @@
+//==============================================================//
+//  ZOBRIST KEYS
+//==============================================================//
+use crate::board::*;
+use crate::square::*;
+
+/// 12 * 64 piece keys, then the side to move, the 4 castling rights and the
+/// 8 en passant files
+const KEY_COUNT: usize = 12 * 64 + 1 + 4 + 8;
+const SIDE: usize = 12 * 64;
+const CASTLING: usize = SIDE + 1;
+const EN_PASSANT: usize = CASTLING + 4;
+/// Random numbers generated at compile time, the same in every build
+const KEYS: [u64; KEY_COUNT] = random_keys(0x9e37_79b9_7f4a_7c15);
+
+/// Key of a piece standing on a square
+pub fn piece_key(color: PieceColor, piece_type: PieceType, square: Square) -> u64 {
+    KEYS[(color.index() * 6 + piece_type.index()) * 64 + square.index()]
+}
+/// Key xored in when Black is to move
+pub fn side_key() -> u64 {
+    KEYS[SIDE]
+}
+pub fn castling_key(castling: CastlingRights) -> u64 {
+    let rights = [
+        castling.white_king_side,
+        castling.white_queen_side,
+        castling.black_king_side,
+        castling.black_queen_side,
+    ];
+    (0..4)
+        .filter(|i| rights[*i])
+        .fold(0, |key, i| key ^ KEYS[CASTLING + i])
+}
+/// Key of the en passant square, only its file matters
+pub fn en_passant_key(en_passant: Option<Square>) -> u64 {
+    en_passant.map_or(0, |square| KEYS[EN_PASSANT + square.file()])
+}
+
+/// SplitMix64 sequence starting from `seed`
+const fn random_keys(mut seed: u64) -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut i = 0;
+    while i < KEY_COUNT {
+        seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+//==============================================================//
+//  TESTS
+//==============================================================//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys = KEYS.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), KEY_COUNT);
+        assert!(!keys.contains(&0));
+    }
+    #[test]
+    fn transpositions_share_a_key() {
+        let mut one = ChessBoard::new();
+        let mut other = ChessBoard::new();
+        for (origin, destination) in &[("g1", "f3"), ("g8", "f6"), ("b1", "c3")] {
+            let mov = one
+                .new_move(origin.parse().unwrap(), destination.parse().unwrap(), None)
+                .unwrap();
+            one.make_move(mov);
+        }
+        for (origin, destination) in &[("b1", "c3"), ("g8", "f6"), ("g1", "f3")] {
+            let mov = other
+                .new_move(origin.parse().unwrap(), destination.parse().unwrap(), None)
+                .unwrap();
+            other.make_move(mov);
+        }
+        assert_eq!(one.hash, other.hash);
+        assert_eq!(one.hash, one.position_key());
+        assert_ne!(one.hash, ChessBoard::new().hash);
+    }
+}